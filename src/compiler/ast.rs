@@ -0,0 +1,288 @@
+use std::fmt;
+
+use super::span::Span;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct File {
+    pub items: Vec<Item>,
+    pub comments: Vec<Comment>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    pub segments: Vec<Ident>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Item {
+    pub kind: ItemKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ItemKind {
+    Fn(FnItem),
+    Struct(StructItem),
+    Enum(EnumItem),
+    Mod(ModItem),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FnItem {
+    pub ident: Ident,
+    pub params: Vec<Ident>,
+    pub body: Block,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructItem {
+    pub ident: Ident,
+    pub fields: Fields,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnumItem {
+    pub ident: Ident,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variant {
+    pub ident: Ident,
+    pub fields: Fields,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fields {
+    Tuple(Vec<Ident>),
+    Map(Vec<Ident>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModItem {
+    pub ident: Ident,
+    pub items: Vec<Item>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub expr: Option<Box<Expr>>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StmtKind {
+    Let { ident: Ident, value: Expr },
+    Expr(Expr),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprKind {
+    Literal(Literal),
+    Path(Path),
+    Tuple(Vec<Expr>),
+    List(Vec<Expr>),
+    Map(Vec<(Expr, Expr)>),
+    Struct {
+        path: Path,
+        fields: Vec<(Ident, Expr)>,
+    },
+    Block(Block),
+
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Assign {
+        target: Box<Expr>,
+        value: Box<Expr>,
+    },
+
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+    },
+    MethodCall {
+        receiver: Box<Expr>,
+        ident: Ident,
+        args: Vec<Expr>,
+    },
+    Field {
+        expr: Box<Expr>,
+        field: Field,
+    },
+    Index {
+        expr: Box<Expr>,
+        index: Box<Expr>,
+    },
+
+    If {
+        cond: Box<Expr>,
+        then_block: Block,
+        else_expr: Option<Box<Expr>>,
+    },
+    While {
+        cond: Box<Expr>,
+        body: Block,
+    },
+    Return(Option<Box<Expr>>),
+    Closure {
+        params: Vec<Ident>,
+        body: Block,
+    },
+}
+
+impl ExprKind {
+    /// Whether the expression ends in a block, and can therefore be used as
+    /// a statement without a trailing `;`.
+    pub fn is_block_like(&self) -> bool {
+        matches!(
+            self,
+            ExprKind::Block(_) | ExprKind::If { .. } | ExprKind::While { .. }
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Literal {
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Field {
+    Named(Ident),
+    Positional(u8, Span),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnaryOp::Negate => write!(f, "-"),
+            UnaryOp::Not => write!(f, "!"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BinaryOp {
+    Or,
+    And,
+
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+
+    BitOr,
+    BitXor,
+    BitAnd,
+    ShiftLeft,
+    ShiftRight,
+
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Power,
+}
+
+impl BinaryOp {
+    /// Binding power of the operator, where a higher number binds tighter.
+    pub fn precedence(self) -> u8 {
+        use self::BinaryOp::*;
+
+        match self {
+            Or => 1,
+            And => 2,
+            Equal | NotEqual | Less | LessEqual | Greater | GreaterEqual => 3,
+            BitOr => 4,
+            BitXor => 5,
+            BitAnd => 6,
+            ShiftLeft | ShiftRight => 7,
+            Add | Subtract => 8,
+            Multiply | Divide | Remainder => 9,
+            Power => 10,
+        }
+    }
+
+    pub fn is_right_assoc(self) -> bool {
+        self == BinaryOp::Power
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::BinaryOp::*;
+
+        let symbol = match self {
+            Or => "||",
+            And => "&&",
+
+            Equal => "==",
+            NotEqual => "!=",
+            Less => "<",
+            LessEqual => "<=",
+            Greater => ">",
+            GreaterEqual => ">=",
+
+            BitOr => "|",
+            BitXor => "^",
+            BitAnd => "&",
+            ShiftLeft => "<<",
+            ShiftRight => ">>",
+
+            Add => "+",
+            Subtract => "-",
+            Multiply => "*",
+            Divide => "/",
+            Remainder => "%",
+            Power => "**",
+        };
+        write!(f, "{}", symbol)
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::{HashMap, HashSet};
+
+use super::ast::{self, ExprKind, StmtKind};
+use super::span::Span;
+
+/// The variables of a function, and of the closures inside it, that are used
+/// by a closure other than the one declaring them.
+#[derive(Debug, Default)]
+pub struct Captures {
+    /// The spans of the identifiers declaring the captured variables.
+    pub vars: HashSet<Span>,
+    /// The number of captured variables declared by each loop body, outside
+    /// of any loop nested in it, keyed by the span of the body. Each
+    /// iteration gets a fresh captured scope for them.
+    pub loop_bodies: HashMap<Span, usize>,
+}
+
+/// Finds the captured variables of the function with `params` and `body`.
+///
+/// Names are resolved the same way the generator resolves them, so that the
+/// generator can keep these variables in a captured scope from the start.
+pub fn captures(params: &[ast::Ident], body: &ast::Block) -> Captures {
+    let mut analysis = Analysis {
+        fns: Vec::new(),
+        loop_bodies: Vec::new(),
+        captures: Captures::default(),
+    };
+    analysis.fun(params, body);
+    analysis.captures
+}
+
+struct Analysis<'a> {
+    // The variables declared in each block of each enclosing function,
+    // innermost last.
+    fns: Vec<Vec<Block<'a>>>,
+    // The loop bodies each enclosing function is in, innermost last.
+    loop_bodies: Vec<Vec<Span>>,
+    captures: Captures,
+}
+
+// The variables declared in a block, along with the loop body declaring them.
+type Block<'a> = Vec<(&'a ast::Ident, Option<Span>)>;
+
+impl<'a> Analysis<'a> {
+    fn fun(&mut self, params: &'a [ast::Ident], body: &'a ast::Block) {
+        self.fns
+            .push(vec![params.iter().map(|param| (param, None)).collect()]);
+        self.loop_bodies.push(Vec::new());
+        self.block(body);
+        self.loop_bodies.pop();
+        self.fns.pop();
+    }
+
+    fn block(&mut self, block: &'a ast::Block) {
+        self.blocks().push(Vec::new());
+
+        for stmt in &block.stmts {
+            match &stmt.kind {
+                StmtKind::Let { ident, value } => {
+                    self.expr(value);
+                    let loop_body =
+                        self.loop_bodies.last().unwrap().last().copied();
+                    self.blocks().last_mut().unwrap().push((ident, loop_body));
+                }
+                StmtKind::Expr(expr) => self.expr(expr),
+            }
+        }
+        if let Some(expr) = &block.expr {
+            self.expr(expr);
+        }
+
+        self.blocks().pop();
+    }
+
+    fn expr(&mut self, expr: &'a ast::Expr) {
+        match &expr.kind {
+            ExprKind::Literal(_) => {}
+            ExprKind::Path(path) => self.path(path),
+            ExprKind::Tuple(exprs) | ExprKind::List(exprs) => self.exprs(exprs),
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            ExprKind::Struct { fields, .. } => {
+                for (_, value) in fields {
+                    self.expr(value);
+                }
+            }
+            ExprKind::Block(block) => self.block(block),
+
+            ExprKind::Unary { expr, .. } => self.expr(expr),
+            ExprKind::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Assign { target, value } => {
+                self.expr(target);
+                self.expr(value);
+            }
+
+            ExprKind::Call { callee, args } => {
+                self.expr(callee);
+                self.exprs(args);
+            }
+            ExprKind::MethodCall { receiver, args, .. } => {
+                self.expr(receiver);
+                self.exprs(args);
+            }
+            ExprKind::Field { expr, .. } => self.expr(expr),
+            ExprKind::Index { expr, index } => {
+                self.expr(expr);
+                self.expr(index);
+            }
+
+            ExprKind::If {
+                cond,
+                then_block,
+                else_expr,
+            } => {
+                self.expr(cond);
+                self.block(then_block);
+                if let Some(else_expr) = else_expr {
+                    self.expr(else_expr);
+                }
+            }
+            ExprKind::While { cond, body } => {
+                self.expr(cond);
+                self.loop_bodies.last_mut().unwrap().push(body.span);
+                self.block(body);
+                self.loop_bodies.last_mut().unwrap().pop();
+            }
+            ExprKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            ExprKind::Closure { params, body } => self.fun(params, body),
+        }
+    }
+
+    fn exprs(&mut self, exprs: &'a [ast::Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn path(&mut self, path: &ast::Path) {
+        let name = match path.segments.as_slice() {
+            [ident] => &ident.name,
+            _ => return,
+        };
+
+        let innermost = self.fns.len() - 1;
+        for (index, blocks) in self.fns.iter().enumerate().rev() {
+            let found = blocks
+                .iter()
+                .rev()
+                .flat_map(|block| block.iter().rev())
+                .find(|(ident, _)| ident.name == *name);
+
+            if let Some(&(ident, loop_body)) = found {
+                if index != innermost && self.captures.vars.insert(ident.span) {
+                    if let Some(loop_body) = loop_body {
+                        *self
+                            .captures
+                            .loop_bodies
+                            .entry(loop_body)
+                            .or_insert(0) += 1;
+                    }
+                }
+                return;
+            }
+        }
+    }
+
+    fn blocks(&mut self) -> &mut Vec<Block<'a>> {
+        self.fns.last_mut().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::ast::ItemKind;
+    use crate::compiler::parser::Parser;
+
+    use super::{captures, Captures};
+
+    /// Analyses the first function of `source`.
+    fn first_fn_captures(source: &str) -> Captures {
+        let (file, diagnostics) = Parser::parse(source);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        match &file.items[0].kind {
+            ItemKind::Fn(fn_item) => captures(&fn_item.params, &fn_item.body),
+            kind => panic!("unexpected item: {:?}", kind),
+        }
+    }
+
+    /// Returns the names of the captured variables in the first function of
+    /// `source`, in the order they are declared.
+    fn captured_names(source: &str) -> Vec<String> {
+        let captures = first_fn_captures(source);
+        let mut spans: Vec<_> = captures.vars.into_iter().collect();
+        spans.sort_by_key(|span| span.start());
+        spans
+            .into_iter()
+            .map(|span| source[span.start()..span.end()].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn captured() {
+        assert_eq!(
+            captured_names(
+                "fn f(a, b) {
+                     let c = 1;
+                     let d = 2;
+                     let g = fn(e) { a + e + { let c = 3; c } };
+                     fn() { fn() { d = b; } }
+                 }",
+            ),
+            vec!["a", "b", "d"],
+        );
+    }
+
+    #[test]
+    fn shadowed() {
+        assert_eq!(
+            captured_names(
+                "fn f(x) {
+                     let y = x;
+                     let y = 2;
+                     fn(x) { x + y }
+                 }",
+            ),
+            vec!["y"],
+        );
+        // The value of a `let` is resolved before its binding.
+        assert_eq!(
+            captured_names("fn f(x) { fn() { let x = x; x } }"),
+            vec!["x"],
+        );
+    }
+
+    #[test]
+    fn loop_bodies() {
+        let source = "fn f() {
+                          let a = 0;
+                          while a < 1 {
+                              let b = 1;
+                              let c = 2;
+                              while true { let d = 3; fn() { a + b + d } }
+                              fn() { c }
+                          }
+                          while false { let e = 4; e }
+                      }";
+        let captures = first_fn_captures(source);
+
+        let mut loop_bodies: Vec<_> =
+            captures.loop_bodies.into_iter().collect();
+        loop_bodies.sort_by_key(|(span, _)| span.start());
+        let counts: Vec<_> =
+            loop_bodies.into_iter().map(|(_, count)| count).collect();
+        assert_eq!(counts, vec![2, 1]);
+    }
+}
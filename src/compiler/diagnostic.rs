@@ -0,0 +1,531 @@
+use std::fmt::{self, Write};
+
+use super::error::Error;
+use super::span::Span;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LabelStyle {
+    Primary,
+    Secondary,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Label {
+    style: LabelStyle,
+    span: Span,
+    message: String,
+}
+
+impl Label {
+    pub fn primary<S: Into<String>>(span: Span, message: S) -> Label {
+        Label {
+            style: LabelStyle::Primary,
+            span,
+            message: message.into(),
+        }
+    }
+
+    pub fn secondary<S: Into<String>>(span: Span, message: S) -> Label {
+        Label {
+            style: LabelStyle::Secondary,
+            span,
+            message: message.into(),
+        }
+    }
+
+    pub fn style(&self) -> LabelStyle {
+        self.style
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    severity: Severity,
+    code: Option<String>,
+    message: String,
+    labels: Vec<Label>,
+    notes: Vec<String>,
+    help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new<S: Into<String>>(severity: Severity, message: S) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code: None,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn error<S: Into<String>>(message: S) -> Diagnostic {
+        Diagnostic::new(Severity::Error, message)
+    }
+
+    pub fn warning<S: Into<String>>(message: S) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, message)
+    }
+
+    pub fn with_code<S: Into<String>>(mut self, code: S) -> Diagnostic {
+        self.code = Some(code.into());
+        self
+    }
+
+    pub fn with_label(mut self, label: Label) -> Diagnostic {
+        self.labels.push(label);
+        self
+    }
+
+    pub fn with_note<S: Into<String>>(mut self, note: S) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help<S: Into<String>>(mut self, help: S) -> Diagnostic {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn code(&self) -> Option<&str> {
+        self.code.as_deref()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn labels(&self) -> &[Label] {
+        &self.labels
+    }
+
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
+
+    pub fn help(&self) -> Option<&str> {
+        self.help.as_deref()
+    }
+
+    /// The span of the first primary label, which is where the diagnostic is
+    /// reported.
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels
+            .iter()
+            .find(|label| label.style == LabelStyle::Primary)
+            .or_else(|| self.labels.first())
+            .map(Label::span)
+    }
+}
+
+impl From<Error> for Diagnostic {
+    fn from(error: Error) -> Diagnostic {
+        use self::Error::*;
+
+        match error {
+            UnexpectedChar { ch, span } => {
+                Diagnostic::error(format!("unexpected character {:?}", ch))
+                    .with_code("E0001")
+                    .with_label(Label::primary(span, "unexpected character"))
+            }
+            UnterminatedString { span } => {
+                Diagnostic::error("unterminated string")
+                    .with_code("E0002")
+                    .with_label(Label::primary(span, "string starts here"))
+                    .with_help("add a closing `\"`")
+            }
+            InvalidEscape { escape, span } => Diagnostic::error(format!(
+                "invalid escape `\\{}`",
+                escape
+            ))
+            .with_code("E0003")
+            .with_label(Label::primary(span, "invalid escape"))
+            .with_note(
+                "valid escapes are `\\n`, `\\r`, `\\t`, `\\0`, `\\\\`, \
+                         `\\\"` and `\\u{...}`",
+            ),
+            InvalidNumber { number, span } => {
+                Diagnostic::error(format!("invalid number `{}`", number))
+                    .with_code("E0004")
+                    .with_label(Label::primary(span, "number out of range"))
+            }
+            UnexpectedToken {
+                expected,
+                found,
+                span,
+            } => Diagnostic::error(format!(
+                "expected {}, found {}",
+                expected, found
+            ))
+            .with_code("E0005")
+            .with_label(Label::primary(span, format!("expected {}", expected))),
+            InvalidAssignTarget { span } => {
+                Diagnostic::error("invalid assignment target")
+                    .with_code("E0006")
+                    .with_label(Label::primary(span, "cannot assign to this"))
+                    .with_help("only variables and fields can be assigned to")
+            }
+            UnresolvedName { name, span } => Diagnostic::error(format!(
+                "cannot find `{}` in this scope",
+                name
+            ))
+            .with_code("E0007")
+            .with_label(Label::primary(span, "not found in this scope")),
+            DuplicateItem {
+                name,
+                span,
+                prev_span,
+            } => Diagnostic::error(format!(
+                "`{}` is defined multiple times",
+                name
+            ))
+            .with_code("E0008")
+            .with_label(Label::primary(span, "redefined here"))
+            .with_label(Label::secondary(
+                prev_span,
+                format!("previous definition of `{}` here", name),
+            )),
+            TooManyParams { span } => Diagnostic::error("too many parameters")
+                .with_code("E0009")
+                .with_label(Label::primary(span, "function defined here"))
+                .with_note("functions can have at most 255 parameters"),
+            TooManyArgs { span } => Diagnostic::error("too many arguments")
+                .with_code("E0010")
+                .with_label(Label::primary(span, "called here"))
+                .with_note("functions can have at most 255 arguments"),
+            TooManyFields { span } => Diagnostic::error("too many fields")
+                .with_code("E0012")
+                .with_label(Label::primary(span, "constructed here"))
+                .with_note("values can have at most 255 fields"),
+            UncapturedVar { name, span } => Diagnostic::error(format!(
+                "`{}` was not captured by its closure",
+                name
+            ))
+            .with_code("E0013")
+            .with_label(Label::primary(span, "used here"))
+            .with_note("this is a bug in the compiler"),
+            TooManyVariants { span } => Diagnostic::error("too many variants")
+                .with_code("E0011")
+                .with_label(Label::primary(span, "enum defined here"))
+                .with_note("enums can have at most 256 variants"),
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+const TAB_WIDTH: usize = 4;
+
+/// Renders diagnostics against the source they were reported in, printing
+/// the offending lines with their labels underlined.
+pub struct Renderer<'a> {
+    name: &'a str,
+    source: &'a str,
+    colored: bool,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(name: &'a str, source: &'a str) -> Renderer<'a> {
+        Renderer {
+            name,
+            source,
+            colored: false,
+        }
+    }
+
+    /// Whether to use ANSI escape codes, for printing to terminals.
+    pub fn colored(mut self, colored: bool) -> Renderer<'a> {
+        self.colored = colored;
+        self
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let mut output = String::new();
+        self.write(&mut output, diagnostic).unwrap();
+        output
+    }
+
+    fn write(&self, w: &mut String, diagnostic: &Diagnostic) -> fmt::Result {
+        let severity_color = diagnostic.severity.color();
+
+        write!(w, "{}", self.paint(severity_color, diagnostic.severity))?;
+        if let Some(code) = &diagnostic.code {
+            let code = format!("[{}]", code);
+            write!(w, "{}", self.paint(severity_color, code))?;
+        }
+        writeln!(
+            w,
+            "{}",
+            self.paint(BOLD, format!(": {}", diagnostic.message))
+        )?;
+
+        let mut labels: Vec<_> = diagnostic.labels.iter().collect();
+        labels.sort_by_key(|label| (label.span.line(), label.span.column()));
+
+        let gutter_width = labels
+            .iter()
+            .map(|label| label.span.line().to_string().len())
+            .max()
+            .unwrap_or(0);
+        let gutter = " ".repeat(gutter_width);
+
+        if let Some(span) = diagnostic.primary_span() {
+            writeln!(
+                w,
+                "{}{} {}:{}:{}",
+                gutter,
+                self.paint(BLUE, "-->"),
+                self.name,
+                span.line(),
+                span.column(),
+            )?;
+            writeln!(w, "{} {}", gutter, self.paint(BLUE, "|"))?;
+
+            let mut prev_line = None;
+            for label in &labels {
+                let line = label.span.line();
+
+                if prev_line != Some(line) {
+                    if prev_line.is_some_and(|prev_line| line > prev_line + 1) {
+                        writeln!(w, "{}", self.paint(BLUE, "..."))?;
+                    }
+
+                    let (text, _) = self.line(label.span);
+                    writeln!(
+                        w,
+                        "{} {} {}",
+                        self.paint(
+                            BLUE,
+                            format!("{:>width$}", line, width = gutter_width)
+                        ),
+                        self.paint(BLUE, "|"),
+                        text.replace('\t', &" ".repeat(TAB_WIDTH)),
+                    )?;
+                }
+                prev_line = Some(line);
+
+                let (text, offset) = self.line(label.span);
+                let indent = expanded_width(&text[..offset.min(text.len())]);
+                let len =
+                    expanded_width(underlined(text, offset, label.span)).max(1);
+
+                let (marker, color) = match label.style {
+                    LabelStyle::Primary => ('^', severity_color),
+                    LabelStyle::Secondary => ('-', BLUE),
+                };
+                let underline = marker.to_string().repeat(len);
+
+                write!(
+                    w,
+                    "{} {} {}",
+                    gutter,
+                    self.paint(BLUE, "|"),
+                    " ".repeat(indent),
+                )?;
+                if label.message.is_empty() {
+                    writeln!(w, "{}", self.paint(color, underline))?;
+                } else {
+                    writeln!(
+                        w,
+                        "{}",
+                        self.paint(
+                            color,
+                            format!("{} {}", underline, label.message)
+                        ),
+                    )?;
+                }
+            }
+
+            if !diagnostic.notes.is_empty() || diagnostic.help.is_some() {
+                writeln!(w, "{} {}", gutter, self.paint(BLUE, "|"))?;
+            }
+        }
+
+        for note in &diagnostic.notes {
+            writeln!(
+                w,
+                "{} {} {}: {}",
+                gutter,
+                self.paint(BLUE, "="),
+                self.paint(BOLD, "note"),
+                note,
+            )?;
+        }
+        if let Some(help) = &diagnostic.help {
+            writeln!(
+                w,
+                "{} {} {}: {}",
+                gutter,
+                self.paint(BLUE, "="),
+                self.paint(BOLD, "help"),
+                help,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the text of the line containing the start of `span`, without
+    /// its line terminator, along with the byte offset of `span` within it.
+    fn line(&self, span: Span) -> (&'a str, usize) {
+        let start = span.start().min(self.source.len());
+        let line_start = self.source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = self.source[start..]
+            .find('\n')
+            .map_or(self.source.len(), |i| start + i);
+
+        let text = self.source[line_start..line_end].trim_end_matches('\r');
+        (text, start - line_start)
+    }
+
+    fn paint<T: fmt::Display>(&self, color: &str, text: T) -> String {
+        if self.colored {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// The part of `span` that lies on the line `text`, which it starts at byte
+/// `offset` of.
+fn underlined(text: &str, offset: usize, span: Span) -> &str {
+    let start = offset.min(text.len());
+    let end = (offset + span.len()).min(text.len());
+    &text[start..end]
+}
+
+fn expanded_width(text: &str) -> usize {
+    text.chars()
+        .map(|ch| if ch == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::parser::Parser;
+    use crate::compiler::span::Span;
+
+    use super::{Diagnostic, Label, Renderer};
+
+    #[test]
+    fn render_plain() {
+        let source = "fn main() {\n    let x = ;\n}\n";
+        let (_, diagnostics) = Parser::parse(source);
+        let diagnostic = diagnostics[0].clone().with_help("add a value");
+
+        assert_eq!(
+            Renderer::new("main.eko", source).render(&diagnostic),
+            "\
+error[E0005]: expected expression, found `;`
+ --> main.eko:2:13
+  |
+2 |     let x = ;
+  |             ^ expected expression
+  |
+  = help: add a value
+",
+        );
+    }
+
+    #[test]
+    fn render_secondary_labels() {
+        let source = "fn f() {}\n\n\nfn f() {}\n";
+        let diagnostic = Diagnostic::error("`f` is defined multiple times")
+            .with_label(Label::primary(Span::new(15, 16, 4, 4), "redefined"))
+            .with_label(Label::secondary(Span::new(3, 4, 1, 4), "first"))
+            .with_note("items must be unique");
+
+        assert_eq!(
+            Renderer::new("f.eko", source).render(&diagnostic),
+            "\
+error: `f` is defined multiple times
+ --> f.eko:4:4
+  |
+1 | fn f() {}
+  |    - first
+...
+4 | fn f() {}
+  |    ^ redefined
+  |
+  = note: items must be unique
+",
+        );
+    }
+
+    #[test]
+    fn render_tabs_and_wide_spans() {
+        let source = "\tlet é = x;";
+        let diagnostic = Diagnostic::warning("unused")
+            .with_code("W0001")
+            .with_label(Label::primary(Span::new(5, 7, 1, 6), ""))
+            .with_label(Label::primary(Span::new(10, 11, 1, 9), "here"));
+
+        assert_eq!(
+            Renderer::new("t.eko", source).render(&diagnostic),
+            "\
+warning[W0001]: unused
+ --> t.eko:1:6
+  |
+1 |     let é = x;
+  |         ^
+  |             ^ here
+",
+        );
+    }
+
+    #[test]
+    fn render_colored() {
+        let source = "fn";
+        let diagnostic = Diagnostic::error("oops")
+            .with_label(Label::primary(Span::new(0, 2, 1, 1), "here"));
+
+        let output = Renderer::new("c.eko", source)
+            .colored(true)
+            .render(&diagnostic);
+
+        assert!(output.starts_with("\x1b[1;31merror\x1b[0m"));
+        assert!(output.contains("\x1b[1;31m^^ here\x1b[0m"));
+    }
+}
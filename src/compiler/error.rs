@@ -0,0 +1,76 @@
+use super::lexer::TokenKind;
+use super::span::Span;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(display = "{}: unexpected character: {:?}", span, ch)]
+    UnexpectedChar { ch: char, span: Span },
+
+    #[error(display = "{}: unterminated string", span)]
+    UnterminatedString { span: Span },
+
+    #[error(display = "{}: invalid escape: \\{}", span, escape)]
+    InvalidEscape { escape: String, span: Span },
+
+    #[error(display = "{}: invalid number: {}", span, number)]
+    InvalidNumber { number: String, span: Span },
+
+    #[error(display = "{}: expected {}, found {}", span, expected, found)]
+    UnexpectedToken {
+        expected: String,
+        found: TokenKind,
+        span: Span,
+    },
+
+    #[error(display = "{}: invalid assignment target", span)]
+    InvalidAssignTarget { span: Span },
+
+    #[error(display = "{}: cannot find `{}` in this scope", span, name)]
+    UnresolvedName { name: String, span: Span },
+
+    #[error(display = "{}: `{}` is defined multiple times", span, name)]
+    DuplicateItem {
+        name: String,
+        span: Span,
+        prev_span: Span,
+    },
+
+    #[error(display = "{}: too many parameters", span)]
+    TooManyParams { span: Span },
+
+    #[error(display = "{}: too many arguments", span)]
+    TooManyArgs { span: Span },
+
+    #[error(display = "{}: too many fields", span)]
+    TooManyFields { span: Span },
+
+    #[error(display = "{}: `{}` was not captured by its closure", span, name)]
+    UncapturedVar { name: String, span: Span },
+
+    #[error(display = "{}: too many variants", span)]
+    TooManyVariants { span: Span },
+}
+
+impl Error {
+    pub fn span(&self) -> Span {
+        use self::Error::*;
+
+        match self {
+            UnexpectedChar { span, .. }
+            | UnterminatedString { span }
+            | InvalidEscape { span, .. }
+            | InvalidNumber { span, .. }
+            | UnexpectedToken { span, .. }
+            | InvalidAssignTarget { span }
+            | UnresolvedName { span, .. }
+            | DuplicateItem { span, .. }
+            | TooManyParams { span }
+            | TooManyArgs { span }
+            | TooManyFields { span }
+            | UncapturedVar { span, .. }
+            | TooManyVariants { span } => *span,
+        }
+    }
+}
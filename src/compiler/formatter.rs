@@ -0,0 +1,958 @@
+use std::fmt::Write;
+use std::iter;
+
+use super::ast::{self, *};
+use super::diagnostic::{Diagnostic, Label};
+use super::parser::Parser;
+use super::span::Span;
+
+/// Formats source files into their canonical layout.
+///
+/// Formatting goes through the AST, so only files that parse without errors
+/// can be formatted.
+pub struct Formatter {
+    max_width: usize,
+    indent_width: usize,
+}
+
+impl Formatter {
+    pub fn new() -> Formatter {
+        Formatter {
+            max_width: 80,
+            indent_width: 4,
+        }
+    }
+
+    pub fn max_width(mut self, max_width: usize) -> Formatter {
+        self.max_width = max_width;
+        self
+    }
+
+    pub fn indent_width(mut self, indent_width: usize) -> Formatter {
+        self.indent_width = indent_width;
+        self
+    }
+
+    pub fn format(
+        &self,
+        source: &str,
+    ) -> std::result::Result<String, Vec<Diagnostic>> {
+        let (file, diagnostics) = Parser::parse(source);
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+
+        let doc = Builder::new(source, &file.comments).file(&file);
+        Ok(print(&doc, self.max_width, self.indent_width))
+    }
+
+    /// Returns a warning pointing at the first line that differs from the
+    /// formatted output, or `None` if `source` is already formatted.
+    pub fn check(
+        &self,
+        source: &str,
+    ) -> std::result::Result<Option<Diagnostic>, Vec<Diagnostic>> {
+        let formatted = self.format(source)?;
+        if formatted == source {
+            return Ok(None);
+        }
+
+        let lines: Vec<_> = source.split('\n').collect();
+        let formatted_lines: Vec<_> = formatted.split('\n').collect();
+        let index = (0..)
+            .find(|&index| lines.get(index) != formatted_lines.get(index))
+            .unwrap()
+            .min(lines.len() - 1);
+
+        let start: usize =
+            lines[..index].iter().map(|line| line.len() + 1).sum();
+        let span = Span::new(start, start + lines[index].len(), index + 1, 1);
+
+        let mut diagnostic = Diagnostic::warning("file is not formatted")
+            .with_label(Label::primary(span, "formatting differs from here"));
+        if let Some(line) = formatted_lines.get(index) {
+            diagnostic = diagnostic.with_note(format!("expected `{}`", line));
+        }
+        Ok(Some(
+            diagnostic.with_help("run `eko fmt` to format the file"),
+        ))
+    }
+}
+
+impl Default for Formatter {
+    fn default() -> Formatter {
+        Formatter::new()
+    }
+}
+
+/// Layout document in the style of Wadler's pretty printer. Lines inside a
+/// group are either all broken or all flat, depending on whether the group
+/// fits in the remaining width.
+enum Doc {
+    Text(String),
+    /// A space when flat, a newline when broken.
+    Line,
+    /// Nothing when flat, a newline when broken.
+    SoftLine,
+    /// Always a newline, which forces the enclosing groups to break.
+    HardLine,
+    /// Text that is only printed when broken, such as a trailing comma.
+    IfBreak(&'static str),
+    Concat(Vec<Doc>),
+    Nest(Box<Doc>),
+    Group(Box<Doc>),
+}
+
+fn text<S: Into<String>>(text: S) -> Doc {
+    Doc::Text(text.into())
+}
+
+fn nest(doc: Doc) -> Doc {
+    Doc::Nest(Box::new(doc))
+}
+
+fn group(doc: Doc) -> Doc {
+    Doc::Group(Box::new(doc))
+}
+
+fn parens(doc: Doc) -> Doc {
+    Doc::Concat(vec![text("("), doc, text(")")])
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+fn print(doc: &Doc, max_width: usize, indent_width: usize) -> String {
+    let mut output = String::new();
+    let mut column = 0;
+    let mut stack = vec![(0, Mode::Break, doc)];
+
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(text) => {
+                output.push_str(text);
+                column += text.chars().count();
+            }
+            Doc::Line if mode == Mode::Flat => {
+                output.push(' ');
+                column += 1;
+            }
+            Doc::SoftLine if mode == Mode::Flat => {}
+            Doc::Line | Doc::SoftLine | Doc::HardLine => {
+                output.truncate(output.trim_end_matches(' ').len());
+                output.push('\n');
+                output.extend(iter::repeat_n(' ', indent));
+                column = indent;
+            }
+            Doc::IfBreak(text) => {
+                if mode == Mode::Break {
+                    output.push_str(text);
+                    column += text.chars().count();
+                }
+            }
+            Doc::Concat(docs) => {
+                stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc)))
+            }
+            Doc::Nest(doc) => stack.push((indent + indent_width, mode, doc)),
+            Doc::Group(doc) => {
+                let width = max_width as isize - column as isize;
+                let mode = if mode == Mode::Flat || fits(doc, &stack, width) {
+                    Mode::Flat
+                } else {
+                    Mode::Break
+                };
+                stack.push((indent, mode, doc));
+            }
+        }
+    }
+
+    let len = output.trim_end().len();
+    output.truncate(len);
+    if !output.is_empty() {
+        output.push('\n');
+    }
+    output
+}
+
+/// Whether `doc` fits in `width` when printed flat, along with whatever
+/// follows it up to the next line break.
+fn fits(doc: &Doc, rest: &[(usize, Mode, &Doc)], mut width: isize) -> bool {
+    let mut rest = rest.iter().rev();
+    let mut stack = vec![(Mode::Flat, doc)];
+
+    while width >= 0 {
+        let (mode, doc) = match stack.pop() {
+            Some(next) => next,
+            None => match rest.next() {
+                Some(&(_, mode, doc)) => (mode, doc),
+                None => return true,
+            },
+        };
+
+        match doc {
+            Doc::Text(text) => width -= text.chars().count() as isize,
+            Doc::Line if mode == Mode::Flat => width -= 1,
+            Doc::SoftLine if mode == Mode::Flat => {}
+            Doc::Line | Doc::SoftLine => return true,
+            Doc::HardLine => return mode == Mode::Break,
+            Doc::IfBreak(text) => {
+                if mode == Mode::Break {
+                    width -= text.chars().count() as isize;
+                }
+            }
+            Doc::Concat(docs) => {
+                stack.extend(docs.iter().rev().map(|doc| (mode, doc)))
+            }
+            Doc::Nest(doc) | Doc::Group(doc) => stack.push((mode, doc)),
+        }
+    }
+    false
+}
+
+/// Anything that is laid out on its own line.
+#[derive(Clone, Copy)]
+enum Node<'n> {
+    Item(&'n Item),
+    Variant(&'n Variant),
+    Field(&'n Ident),
+    Stmt(&'n Stmt),
+    Expr(&'n Expr),
+}
+
+impl<'n> Node<'n> {
+    fn span(self) -> Span {
+        match self {
+            Node::Item(item) => item.span,
+            Node::Variant(variant) => variant.span,
+            Node::Field(ident) => ident.span,
+            Node::Stmt(stmt) => stmt.span,
+            Node::Expr(expr) => expr.span,
+        }
+    }
+}
+
+/// Builds the layout document for a file.
+///
+/// Comments are not part of the AST, so they are emitted in order before the
+/// first line-level node that follows them, or after the node that ends on
+/// the same line.
+struct Builder<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+    comments: &'a [Comment],
+    next_comment: usize,
+}
+
+impl<'a> Builder<'a> {
+    fn new(source: &'a str, comments: &'a [Comment]) -> Builder<'a> {
+        let line_starts = iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+
+        Builder {
+            source,
+            line_starts,
+            comments,
+            next_comment: 0,
+        }
+    }
+
+    fn file(&mut self, file: &File) -> Doc {
+        let nodes = file.items.iter().map(Node::Item).collect();
+        self.lines(nodes, self.source.len(), true)
+    }
+
+    fn item(&mut self, item: &Item) -> Doc {
+        match &item.kind {
+            ItemKind::Fn(fun) => Doc::Concat(vec![
+                text(format!("fn {}", fun.ident.name)),
+                self.delimited(
+                    "(",
+                    fun.params.iter().map(ident).collect(),
+                    ")",
+                ),
+                text(" "),
+                self.block(&fun.body),
+            ]),
+            ItemKind::Struct(strukt) => {
+                let name = text(format!("struct {}", strukt.ident.name));
+                match &strukt.fields {
+                    Fields::Tuple(fields) if fields.is_empty() => {
+                        Doc::Concat(vec![name, text(";")])
+                    }
+                    Fields::Tuple(fields) => Doc::Concat(vec![
+                        name,
+                        self.delimited(
+                            "(",
+                            fields.iter().map(ident).collect(),
+                            ")",
+                        ),
+                        text(";"),
+                    ]),
+                    Fields::Map(fields) => {
+                        let nodes = fields.iter().map(Node::Field).collect();
+                        let body = self.braced(nodes, item.span.end(), false);
+                        Doc::Concat(vec![name, text(" "), body])
+                    }
+                }
+            }
+            ItemKind::Enum(enu) => {
+                let nodes = enu.variants.iter().map(Node::Variant).collect();
+                Doc::Concat(vec![
+                    text(format!("enum {} ", enu.ident.name)),
+                    self.braced(nodes, item.span.end(), false),
+                ])
+            }
+            ItemKind::Mod(modu) => {
+                let nodes = modu.items.iter().map(Node::Item).collect();
+                Doc::Concat(vec![
+                    text(format!("mod {} ", modu.ident.name)),
+                    self.braced(nodes, item.span.end(), true),
+                ])
+            }
+        }
+    }
+
+    fn variant(&mut self, variant: &Variant) -> Doc {
+        let fields = match &variant.fields {
+            Fields::Tuple(fields) if fields.is_empty() => text(""),
+            Fields::Tuple(fields) => {
+                self.delimited("(", fields.iter().map(ident).collect(), ")")
+            }
+            Fields::Map(fields) if fields.is_empty() => text(" {}"),
+            Fields::Map(fields) => Doc::Concat(vec![
+                text(" "),
+                group(Doc::Concat(vec![
+                    text("{"),
+                    nest(Doc::Concat(vec![
+                        Doc::Line,
+                        join(fields.iter().map(ident).collect()),
+                        Doc::IfBreak(","),
+                    ])),
+                    Doc::Line,
+                    text("}"),
+                ])),
+            ]),
+        };
+        Doc::Concat(vec![text(variant.ident.name.clone()), fields, text(",")])
+    }
+
+    fn block(&mut self, block: &Block) -> Doc {
+        let mut nodes: Vec<_> = block.stmts.iter().map(Node::Stmt).collect();
+        nodes.extend(block.expr.as_deref().map(Node::Expr));
+        self.braced(nodes, block.span.end(), false)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Doc {
+        match &stmt.kind {
+            StmtKind::Let { ident, value } => Doc::Concat(vec![
+                text(format!("let {} = ", ident.name)),
+                self.expr(value),
+                text(";"),
+            ]),
+            StmtKind::Expr(expr) if expr.kind.is_block_like() => {
+                self.expr(expr)
+            }
+            StmtKind::Expr(expr) => {
+                Doc::Concat(vec![self.expr(expr), text(";")])
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Doc {
+        use self::ExprKind::*;
+
+        match &expr.kind {
+            Literal(literal) => text(literal_text(literal)),
+            Path(path) => text(path_text(path)),
+            Tuple(exprs) if exprs.len() == 1 => group(Doc::Concat(vec![
+                text("("),
+                nest(Doc::Concat(vec![Doc::SoftLine, self.expr(&exprs[0])])),
+                text(","),
+                Doc::SoftLine,
+                text(")"),
+            ])),
+            Tuple(exprs) => {
+                let docs = exprs.iter().map(|expr| self.expr(expr)).collect();
+                self.delimited("(", docs, ")")
+            }
+            List(exprs) => {
+                let docs = exprs.iter().map(|expr| self.expr(expr)).collect();
+                self.delimited("[", docs, "]")
+            }
+            Map(entries) if entries.is_empty() => text("[:]"),
+            Map(entries) => {
+                let docs = entries
+                    .iter()
+                    .map(|(key, value)| {
+                        Doc::Concat(vec![
+                            self.expr(key),
+                            text(": "),
+                            self.expr(value),
+                        ])
+                    })
+                    .collect();
+                self.delimited("[", docs, "]")
+            }
+            Struct { path, fields } if fields.is_empty() => {
+                text(format!("{} {{}}", path_text(path)))
+            }
+            Struct { path, fields } => {
+                let docs = fields
+                    .iter()
+                    .map(|(ident, value)| {
+                        Doc::Concat(vec![
+                            text(format!("{}: ", ident.name)),
+                            self.expr(value),
+                        ])
+                    })
+                    .collect();
+                Doc::Concat(vec![
+                    text(format!("{} ", path_text(path))),
+                    group(Doc::Concat(vec![
+                        text("{"),
+                        nest(Doc::Concat(vec![
+                            Doc::Line,
+                            join(docs),
+                            Doc::IfBreak(","),
+                        ])),
+                        Doc::Line,
+                        text("}"),
+                    ])),
+                ])
+            }
+            Block(block) => self.block(block),
+
+            Unary { op, expr } => {
+                let operand = self.expr(expr);
+                let operand = if precedence(expr) < UNARY_PRECEDENCE {
+                    parens(operand)
+                } else {
+                    operand
+                };
+                Doc::Concat(vec![text(op.to_string()), operand])
+            }
+            Binary { op, left, right } => {
+                let mut docs = Vec::new();
+                self.binary(*op, left, right, &mut docs);
+                group(Doc::Concat(docs))
+            }
+            Assign { target, value } => Doc::Concat(vec![
+                self.expr(target),
+                text(" = "),
+                self.expr(value),
+            ]),
+
+            Call { callee, args } => {
+                let args = args.iter().map(|arg| self.expr(arg)).collect();
+                Doc::Concat(vec![
+                    self.receiver(callee),
+                    self.delimited("(", args, ")"),
+                ])
+            }
+            MethodCall {
+                receiver,
+                ident,
+                args,
+            } => {
+                let args = args.iter().map(|arg| self.expr(arg)).collect();
+                Doc::Concat(vec![
+                    self.receiver(receiver),
+                    text(format!(".{}", ident.name)),
+                    self.delimited("(", args, ")"),
+                ])
+            }
+            Field { expr, field } => {
+                let field = match field {
+                    ast::Field::Named(ident) => ident.name.clone(),
+                    ast::Field::Positional(index, _) => index.to_string(),
+                };
+                Doc::Concat(vec![
+                    self.receiver(expr),
+                    text(format!(".{}", field)),
+                ])
+            }
+            Index { expr, index } => Doc::Concat(vec![
+                self.receiver(expr),
+                text("["),
+                self.expr(index),
+                text("]"),
+            ]),
+
+            If {
+                cond,
+                then_block,
+                else_expr,
+            } => {
+                let mut docs = vec![
+                    text("if "),
+                    self.cond(cond),
+                    text(" "),
+                    self.block(then_block),
+                ];
+                if let Some(else_expr) = else_expr {
+                    docs.push(text(" else "));
+                    docs.push(self.expr(else_expr));
+                }
+                Doc::Concat(docs)
+            }
+            While { cond, body } => Doc::Concat(vec![
+                text("while "),
+                self.cond(cond),
+                text(" "),
+                self.block(body),
+            ]),
+            Closure { params, body } => Doc::Concat(vec![
+                text("fn"),
+                self.delimited("(", params.iter().map(ident).collect(), ")"),
+                text(" "),
+                self.block(body),
+            ]),
+            Return(None) => text("return"),
+            Return(Some(expr)) => {
+                Doc::Concat(vec![text("return "), self.expr(expr)])
+            }
+        }
+    }
+
+    /// Pushes the operands of a chain of operators with the same precedence,
+    /// so that the whole chain breaks before each operator at once.
+    fn binary(
+        &mut self,
+        op: BinaryOp,
+        left: &Expr,
+        right: &Expr,
+        docs: &mut Vec<Doc>,
+    ) {
+        let prec = op.precedence();
+
+        match &left.kind {
+            ExprKind::Binary {
+                op: left_op,
+                left: left_left,
+                right: left_right,
+            } if left_op.precedence() == prec && !op.is_right_assoc() => {
+                self.binary(*left_op, left_left, left_right, docs)
+            }
+            _ => {
+                let left_prec = precedence(left);
+                let doc = self.expr(left);
+                if left_prec < prec
+                    || (left_prec == prec && op.is_right_assoc())
+                    || (left_prec == UNARY_PRECEDENCE && op == BinaryOp::Power)
+                {
+                    docs.push(parens(doc));
+                } else {
+                    docs.push(doc);
+                }
+            }
+        }
+
+        let right_prec = precedence(right);
+        let mut doc = self.expr(right);
+        if right_prec < prec || (right_prec == prec && !op.is_right_assoc()) {
+            doc = parens(doc);
+        }
+        docs.push(nest(Doc::Concat(vec![
+            Doc::Line,
+            text(format!("{} ", op)),
+            doc,
+        ])));
+    }
+
+    /// The condition of an `if` or `while`, in parentheses if it has a struct
+    /// literal that would otherwise be parsed as the body.
+    fn cond(&mut self, expr: &Expr) -> Doc {
+        let doc = self.expr(expr);
+        if has_struct_literal(expr) {
+            parens(doc)
+        } else {
+            doc
+        }
+    }
+
+    fn receiver(&mut self, expr: &Expr) -> Doc {
+        let doc = self.expr(expr);
+        if precedence(expr) < POSTFIX_PRECEDENCE {
+            parens(doc)
+        } else {
+            doc
+        }
+    }
+
+    /// Comma separated list that is either on one line or has one element
+    /// per line with a trailing comma.
+    fn delimited(&mut self, open: &str, docs: Vec<Doc>, close: &str) -> Doc {
+        if docs.is_empty() {
+            return text(format!("{}{}", open, close));
+        }
+
+        group(Doc::Concat(vec![
+            text(open),
+            nest(Doc::Concat(vec![
+                Doc::SoftLine,
+                join(docs),
+                Doc::IfBreak(","),
+            ])),
+            Doc::SoftLine,
+            text(close),
+        ]))
+    }
+
+    /// Braces around `nodes`, each on its own line, along with any comments
+    /// before `end`.
+    fn braced(&mut self, nodes: Vec<Node>, end: usize, blank: bool) -> Doc {
+        if nodes.is_empty() && self.peek_comment(end).is_none() {
+            return text("{}");
+        }
+
+        Doc::Concat(vec![
+            text("{"),
+            nest(Doc::Concat(vec![
+                Doc::HardLine,
+                self.lines(nodes, end, blank),
+            ])),
+            Doc::HardLine,
+            text("}"),
+        ])
+    }
+
+    /// Lays out `nodes` and the comments before `end` one per line. Blank
+    /// lines between nodes are kept, or always inserted if `blank` is set.
+    fn lines(&mut self, nodes: Vec<Node>, end: usize, blank: bool) -> Doc {
+        let mut docs = Vec::new();
+        let mut prev_end = None;
+
+        for node in nodes {
+            let span = node.span();
+
+            let mut force_blank = blank;
+            while let Some(comment) = self.take_comment(span.start()) {
+                self.separate(&mut docs, prev_end, comment.span, force_blank);
+                docs.push(text(comment.text.clone()));
+                prev_end = Some(comment.span.end());
+                force_blank = false;
+            }
+            self.separate(&mut docs, prev_end, span, force_blank);
+
+            docs.push(match node {
+                Node::Item(item) => self.item(item),
+                Node::Variant(variant) => self.variant(variant),
+                Node::Field(ident) => text(format!("{},", ident.name)),
+                Node::Stmt(stmt) => self.stmt(stmt),
+                Node::Expr(expr) => self.expr(expr),
+            });
+            prev_end = Some(span.end());
+
+            if let Some(comment) = self.take_trailing_comment(span.end()) {
+                docs.push(text(format!(" {}", comment.text)));
+                prev_end = Some(comment.span.end());
+            }
+        }
+
+        while let Some(comment) = self.take_comment(end) {
+            self.separate(&mut docs, prev_end, comment.span, false);
+            docs.push(text(comment.text.clone()));
+            prev_end = Some(comment.span.end());
+        }
+
+        Doc::Concat(docs)
+    }
+
+    fn separate(
+        &self,
+        docs: &mut Vec<Doc>,
+        prev_end: Option<usize>,
+        span: Span,
+        force_blank: bool,
+    ) {
+        if let Some(prev_end) = prev_end {
+            docs.push(Doc::HardLine);
+
+            let between = &self.source[prev_end..span.start()];
+            if force_blank || between.matches('\n').count() > 1 {
+                docs.push(Doc::HardLine);
+            }
+        }
+    }
+
+    fn peek_comment(&self, before: usize) -> Option<&'a Comment> {
+        self.comments
+            .get(self.next_comment)
+            .filter(|comment| comment.span.start() < before)
+    }
+
+    fn take_comment(&mut self, before: usize) -> Option<&'a Comment> {
+        let comment = self.peek_comment(before)?;
+        self.next_comment += 1;
+        Some(comment)
+    }
+
+    fn take_trailing_comment(&mut self, end: usize) -> Option<&'a Comment> {
+        let line = self.line_of(end.saturating_sub(1));
+        let comment = self.comments.get(self.next_comment)?;
+        if comment.span.start() < end
+            || self.line_of(comment.span.start()) != line
+        {
+            return None;
+        }
+
+        self.next_comment += 1;
+        Some(comment)
+    }
+
+    fn line_of(&self, pos: usize) -> usize {
+        match self.line_starts.binary_search(&pos) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        }
+    }
+}
+
+const UNARY_PRECEDENCE: u8 = 11;
+const POSTFIX_PRECEDENCE: u8 = 12;
+
+/// How tightly an expression binds, which decides where parentheses are
+/// needed. Assignments and returns extend as far right as possible, so they
+/// bind the loosest.
+fn precedence(expr: &Expr) -> u8 {
+    match &expr.kind {
+        ExprKind::Assign { .. } | ExprKind::Return(_) => 0,
+        ExprKind::Binary { op, .. } => op.precedence(),
+        ExprKind::Unary { .. } => UNARY_PRECEDENCE,
+        _ => POSTFIX_PRECEDENCE,
+    }
+}
+
+/// Whether `expr` has a struct literal outside of any delimiters.
+fn has_struct_literal(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Struct { .. } => true,
+        ExprKind::Unary { expr, .. }
+        | ExprKind::Call { callee: expr, .. }
+        | ExprKind::MethodCall { receiver: expr, .. }
+        | ExprKind::Field { expr, .. }
+        | ExprKind::Index { expr, .. }
+        | ExprKind::Return(Some(expr)) => has_struct_literal(expr),
+        ExprKind::Binary { left, right, .. }
+        | ExprKind::Assign {
+            target: left,
+            value: right,
+        } => has_struct_literal(left) || has_struct_literal(right),
+        _ => false,
+    }
+}
+
+fn join(docs: Vec<Doc>) -> Doc {
+    let mut joined = Vec::new();
+    for (index, doc) in docs.into_iter().enumerate() {
+        if index > 0 {
+            joined.push(text(","));
+            joined.push(Doc::Line);
+        }
+        joined.push(doc);
+    }
+    Doc::Concat(joined)
+}
+
+fn ident(ident: &Ident) -> Doc {
+    text(ident.name.clone())
+}
+
+fn path_text(path: &Path) -> String {
+    let segments: Vec<_> = path
+        .segments
+        .iter()
+        .map(|ident| ident.name.as_str())
+        .collect();
+    segments.join("::")
+}
+
+fn literal_text(literal: &Literal) -> String {
+    match literal {
+        Literal::Boolean(boolean) => boolean.to_string(),
+        Literal::Integer(integer) => integer.to_string(),
+        Literal::Float(float) => {
+            // `Display` never uses an exponent, which the lexer would reject.
+            let mut float = float.to_string();
+            if !float.contains('.') {
+                float.push_str(".0");
+            }
+            float
+        }
+        Literal::String(string) => {
+            let mut literal = String::from("\"");
+            for ch in string.chars() {
+                match ch {
+                    '"' => literal.push_str("\\\""),
+                    '\\' => literal.push_str("\\\\"),
+                    '\n' => literal.push_str("\\n"),
+                    '\r' => literal.push_str("\\r"),
+                    '\t' => literal.push_str("\\t"),
+                    '\0' => literal.push_str("\\0"),
+                    ch if ch.is_control() => {
+                        write!(literal, "\\u{{{:x}}}", ch as u32).unwrap()
+                    }
+                    ch => literal.push(ch),
+                }
+            }
+            literal.push('"');
+            literal
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str) -> String {
+        let formatted = Formatter::new().format(source).unwrap();
+        assert_eq!(Formatter::new().format(&formatted).unwrap(), formatted);
+        formatted
+    }
+
+    #[test]
+    fn items() {
+        assert_eq!(
+            format(
+                "fn main( ) { let x=1;x }struct Unit();\
+                 struct Point{x,y} enum Shape{Circle(r),Rect{w,h},Empty}\
+                 mod m{fn f(a,b){}fn g(){}}"
+            ),
+            "fn main() {\n    let x = 1;\n    x\n}\n\n\
+             struct Unit;\n\n\
+             struct Point {\n    x,\n    y,\n}\n\n\
+             enum Shape {\n    Circle(r),\n    Rect { w, h },\n    Empty,\n}\n\n\
+             mod m {\n    fn f(a, b) {}\n\n    fn g() {}\n}\n",
+        );
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(
+            format(
+                "fn main() { a=-(b+c)*d**-e; (-a)**b; -a**b; a-(b-c); \
+                 (a+b).c(d,e).0; (1,); (); if a {1} else if b {2} else {3} \
+                 while !x {return;} f(\"a\\\"\\n\", 1.0, 2.50) }"
+            ),
+            "fn main() {\n    \
+             a = -(b + c) * d ** -e;\n    \
+             (-a) ** b;\n    \
+             -(a ** b);\n    \
+             a - (b - c);\n    \
+             (a + b).c(d, e).0;\n    \
+             (1,);\n    \
+             ();\n    \
+             if a {\n        1\n    } else if b {\n        2\n    } else {\n        3\n    }\n    \
+             while !x {\n        return;\n    }\n    \
+             f(\"a\\\"\\n\", 1.0, 2.5)\n}\n",
+        );
+    }
+
+    #[test]
+    fn closures() {
+        assert_eq!(
+            format("fn main() { let f=fn(x,y){x+y}; apply(fn(){}, f) }"),
+            "fn main() {\n    \
+             let f = fn(x, y) {\n        x + y\n    };\n    \
+             apply(fn() {}, f)\n}\n",
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            format("fn main() { let xs=[1,[2,3],[ ]]; xs[0]=(xs)[1][-1]; }"),
+            "fn main() {\n    \
+             let xs = [1, [2, 3], []];\n    \
+             xs[0] = xs[1][-1];\n}\n",
+        );
+    }
+
+    #[test]
+    fn maps() {
+        assert_eq!(
+            format("fn main() { let m=[\"a\":1,2:[ : ]]; m[2]; }"),
+            "fn main() {\n    \
+             let m = [\"a\": 1, 2: [:]];\n    \
+             m[2];\n}\n",
+        );
+    }
+
+    #[test]
+    fn struct_literals() {
+        assert_eq!(
+            format("fn main() { if (P{x:1,y:P{}})==p { Q{ } } }"),
+            "fn main() {\n    \
+             if (P { x: 1, y: P {} } == p) {\n        Q {}\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn max_width() {
+        let source = "fn main() { call(first, second) + other(third); }";
+        let formatter = Formatter::new().max_width(20);
+
+        assert_eq!(
+            formatter.format(source).unwrap(),
+            "fn main() {\n    \
+             call(\n        first,\n        second,\n    )\n        \
+             + other(\n            third,\n        );\n}\n",
+        );
+        assert_eq!(
+            Formatter::new().max_width(30).format(source).unwrap(),
+            "fn main() {\n    call(first, second)\n        + other(third);\n}\n",
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let source = "// header\n\n\
+                      // about main\n\
+                      fn main() { // trailing\n\
+                      let x = 1; // one\n\n\n\
+                      // before y\n\
+                      let y = 2;\n\
+                      // end\n\
+                      }\n\
+                      fn empty() {\n\
+                      // nothing\n\
+                      }\n\
+                      // eof\n";
+
+        assert_eq!(
+            format(source),
+            "// header\n\n\
+             // about main\n\
+             fn main() {\n    \
+             // trailing\n    \
+             let x = 1; // one\n\n    \
+             // before y\n    \
+             let y = 2;\n    \
+             // end\n\
+             }\n\n\
+             fn empty() {\n    // nothing\n}\n\
+             // eof\n",
+        );
+    }
+
+    #[test]
+    fn check() {
+        let formatter = Formatter::new();
+
+        assert!(formatter.check("fn main() {}\n").unwrap().is_none());
+        assert!(formatter.check("fn main( {").is_err());
+
+        let diagnostic = formatter
+            .check("fn main() {}\nfn  other() {}\n")
+            .unwrap()
+            .unwrap();
+        let span = diagnostic.primary_span().unwrap();
+        assert_eq!((span.line(), span.start(), span.end()), (2, 13, 27));
+        assert_eq!(diagnostic.notes(), ["expected ``"]);
+    }
+}
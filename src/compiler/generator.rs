@@ -1,18 +1,47 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
 use eko_gc::Arena;
 
-use crate::core::fun::Chunk;
-use crate::core::instr::Instr;
+use crate::core::fun::{Chunk, Fn};
+use crate::core::ident::Ident;
+use crate::core::instr::{Fields, Instr};
+use crate::core::modu::Mod;
+use crate::core::typ::{self, EnumVariant, MapData, StructProto, Type};
+use crate::core::value::{self, Value};
+
+use super::ast::{self, BinaryOp, ExprKind, ItemKind, StmtKind, UnaryOp};
+use super::capture::{self, Captures};
+use super::error::{Error, Result};
+use super::span::Span;
+
+/// A position in a chunk that jumps can target before it is known.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Label(usize);
 
+#[derive(Default)]
 pub struct ChunkBuilder<'gc> {
     local_scope_len: usize,
+    captured_scope_len: usize,
     instrs: Vec<Instr<'gc>>,
+    lines: Vec<Option<usize>>,
+    line: Option<usize>,
+    labels: Vec<Option<usize>>,
+    // Jumps to labels that have not been set yet, to be back-patched once
+    // they are.
+    pending_jumps: Vec<(usize, Label)>,
 }
 
 impl<'gc> ChunkBuilder<'gc> {
     pub fn new() -> ChunkBuilder<'gc> {
         ChunkBuilder {
             local_scope_len: 0,
+            captured_scope_len: 0,
             instrs: Vec::new(),
+            lines: Vec::new(),
+            line: None,
+            labels: Vec::new(),
+            pending_jumps: Vec::new(),
         }
     }
 
@@ -22,11 +51,1806 @@ impl<'gc> ChunkBuilder<'gc> {
         local_scope_len
     }
 
+    pub fn next_captured_var(&mut self) -> usize {
+        let captured_scope_len = self.captured_scope_len;
+        self.captured_scope_len += 1;
+        captured_scope_len
+    }
+
     pub fn instr(&mut self, instr: Instr<'gc>) {
         self.instrs.push(instr);
+        self.lines.push(self.line);
+    }
+
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+
+    /// Sets the source line of the instructions that follow.
+    pub fn set_line(&mut self, line: Option<usize>) {
+        self.line = line;
+    }
+
+    pub fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Sets `label` to the next instruction, patching the jumps to it.
+    pub fn set_label(&mut self, label: Label) {
+        let index = self.instrs.len();
+        self.labels[label.0] = Some(index);
+
+        let instrs = &mut self.instrs;
+        self.pending_jumps.retain(|&(instr_index, pending_label)| {
+            if pending_label != label {
+                return true;
+            }
+            match &mut instrs[instr_index] {
+                Instr::Jump { index: target }
+                | Instr::JumpIfTrue { index: target }
+                | Instr::JumpIfFalse { index: target } => *target = index,
+                _ => unreachable!(),
+            }
+            false
+        });
+    }
+
+    pub fn jump(&mut self, label: Label) {
+        let index = self.label_index(label);
+        self.instr(Instr::Jump { index });
+    }
+
+    pub fn jump_if_true(&mut self, label: Label) {
+        let index = self.label_index(label);
+        self.instr(Instr::JumpIfTrue { index });
+    }
+
+    pub fn jump_if_false(&mut self, label: Label) {
+        let index = self.label_index(label);
+        self.instr(Instr::JumpIfFalse { index });
+    }
+
+    /// Returns the index of `label`, or a placeholder to be patched by
+    /// `set_label` if it is not set yet. Must be called right before pushing
+    /// the jump.
+    fn label_index(&mut self, label: Label) -> usize {
+        match self.labels[label.0] {
+            Some(index) => index,
+            None => {
+                self.pending_jumps.push((self.instrs.len(), label));
+                0
+            }
+        }
     }
 
     pub fn build(self, arena: &Arena<'gc>) -> Chunk<'gc> {
-        Chunk::new(arena, self.local_scope_len, self.instrs)
+        assert!(
+            self.pending_jumps.is_empty(),
+            "jump to a label that was never set",
+        );
+        Chunk::new(
+            arena,
+            self.local_scope_len,
+            self.captured_scope_len,
+            self.instrs,
+            self.lines,
+        )
+    }
+}
+
+pub struct Generator<'a, 'gc> {
+    arena: &'a Arena<'gc>,
+    mod_scopes: Vec<ModScope<'gc>>,
+    fn_scopes: Vec<FnScope<'gc>>,
+    // The variables of the current function item that closures capture.
+    captures: Captures,
+}
+
+impl<'a, 'gc> Generator<'a, 'gc> {
+    pub fn new(arena: &'a Arena<'gc>) -> Generator<'a, 'gc> {
+        Generator {
+            arena,
+            mod_scopes: Vec::new(),
+            fn_scopes: Vec::new(),
+            captures: Captures::default(),
+        }
+    }
+
+    /// Generates every item in `file` into `modu`. Nested `mod` items become
+    /// child modules of `modu`.
+    pub fn generate(&mut self, modu: Mod<'gc>, file: &ast::File) -> Result<()> {
+        self.mod_scopes.clear();
+        self.mod_scopes.push(ModScope::new(modu, None, file.span));
+
+        // All the items are declared before any function is generated, so
+        // that functions can call each other regardless of their order.
+        self.declare_items(0, &file.items)?;
+        self.generate_items(0, &file.items)
+    }
+
+    fn declare_items(
+        &mut self,
+        mod_index: usize,
+        items: &[ast::Item],
+    ) -> Result<()> {
+        for item in items {
+            match &item.kind {
+                ItemKind::Fn(fn_item) => {
+                    let ident = &fn_item.ident;
+                    self.check_unique_item(mod_index, ident)?;
+                    self.mod_scopes[mod_index]
+                        .fns
+                        .insert(ident.name.clone(), ident.span);
+                }
+                ItemKind::Mod(mod_item) => {
+                    let ident = &mod_item.ident;
+                    if let Some(&prev_index) =
+                        self.mod_scopes[mod_index].child_mods.get(&ident.name)
+                    {
+                        return Err(Error::DuplicateItem {
+                            name: ident.name.clone(),
+                            span: ident.span,
+                            prev_span: self.mod_scopes[prev_index].span,
+                        });
+                    }
+
+                    let modu = Mod::with_parent_mod(
+                        self.arena,
+                        Ident::new_string(self.arena, ident.name.clone()),
+                        self.mod_scopes[mod_index].modu.clone(),
+                    );
+
+                    let child_index = self.mod_scopes.len();
+                    self.mod_scopes.push(ModScope::new(
+                        modu,
+                        Some(mod_index),
+                        ident.span,
+                    ));
+                    self.mod_scopes[mod_index]
+                        .child_mods
+                        .insert(ident.name.clone(), child_index);
+
+                    self.declare_items(child_index, &mod_item.items)?;
+                }
+                ItemKind::Struct(struct_item) => {
+                    let ident = &struct_item.ident;
+                    let proto =
+                        self.struct_proto(&struct_item.fields, ident)?;
+                    let typ = typ::Struct::new(
+                        self.arena,
+                        Ident::new_string(self.arena, ident.name.clone()),
+                        proto,
+                    );
+                    self.declare_type(mod_index, ident, Type::Struct(typ))?;
+                }
+                ItemKind::Enum(enum_item) => {
+                    let ident = &enum_item.ident;
+                    if enum_item.variants.len() > usize::from(u8::MAX) + 1 {
+                        return Err(Error::TooManyVariants {
+                            span: ident.span,
+                        });
+                    }
+                    check_unique(
+                        enum_item.variants.iter().map(|variant| &variant.ident),
+                    )?;
+
+                    let mut variants = Vec::new();
+                    for variant in &enum_item.variants {
+                        let proto =
+                            self.struct_proto(&variant.fields, &variant.ident)?;
+                        variants.push(EnumVariant::new(
+                            Ident::new_string(
+                                self.arena,
+                                variant.ident.name.clone(),
+                            ),
+                            proto,
+                        ));
+                    }
+                    let typ = typ::Enum::new(
+                        self.arena,
+                        Ident::new_string(self.arena, ident.name.clone()),
+                        variants,
+                    );
+                    self.declare_type(mod_index, ident, Type::Enum(typ))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fails if an item named `ident` is already declared in the module.
+    /// Functions and types share a namespace, since both are used as values.
+    fn check_unique_item(
+        &self,
+        mod_index: usize,
+        ident: &ast::Ident,
+    ) -> Result<()> {
+        let mod_scope = &self.mod_scopes[mod_index];
+        let prev_span = mod_scope
+            .fns
+            .get(&ident.name)
+            .or_else(|| mod_scope.types.get(&ident.name).map(|(_, span)| span));
+
+        match prev_span {
+            Some(&prev_span) => Err(Error::DuplicateItem {
+                name: ident.name.clone(),
+                span: ident.span,
+                prev_span,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn declare_type(
+        &mut self,
+        mod_index: usize,
+        ident: &ast::Ident,
+        typ: Type<'gc>,
+    ) -> Result<()> {
+        self.check_unique_item(mod_index, ident)?;
+
+        let mod_scope = &mut self.mod_scopes[mod_index];
+        mod_scope.modu.set_type(
+            Ident::new_string(self.arena, ident.name.clone()),
+            typ.clone(),
+        );
+        mod_scope
+            .types
+            .insert(ident.name.clone(), (typ, ident.span));
+        Ok(())
+    }
+
+    fn struct_proto(
+        &self,
+        fields: &ast::Fields,
+        ident: &ast::Ident,
+    ) -> Result<StructProto<'gc>> {
+        match fields {
+            ast::Fields::Tuple(fields) => {
+                let len = u8::try_from(fields.len())
+                    .map_err(|_| Error::TooManyFields { span: ident.span })?;
+                Ok(StructProto::Tuple(len))
+            }
+            ast::Fields::Map(fields) => {
+                check_unique(fields)?;
+                let fields = fields
+                    .iter()
+                    .map(|field| {
+                        Ident::new_string(self.arena, field.name.clone())
+                    })
+                    .collect();
+                Ok(StructProto::Map(MapData::new(fields)))
+            }
+        }
+    }
+
+    fn generate_items(
+        &mut self,
+        mod_index: usize,
+        items: &[ast::Item],
+    ) -> Result<()> {
+        for item in items {
+            match &item.kind {
+                ItemKind::Fn(fn_item) => {
+                    let fun = self.generate_fn(mod_index, fn_item)?;
+                    self.mod_scopes[mod_index]
+                        .modu
+                        .set_fn(fun.ident().clone(), fun);
+                }
+                ItemKind::Mod(mod_item) => {
+                    let child_index = self.mod_scopes[mod_index].child_mods
+                        [&mod_item.ident.name];
+                    self.generate_items(child_index, &mod_item.items)?;
+                }
+                ItemKind::Struct(_) | ItemKind::Enum(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn generate_fn(
+        &mut self,
+        mod_index: usize,
+        fn_item: &ast::FnItem,
+    ) -> Result<Fn<'gc>> {
+        let arity = u8::try_from(fn_item.params.len()).map_err(|_| {
+            Error::TooManyParams {
+                span: fn_item.ident.span,
+            }
+        })?;
+
+        self.captures = capture::captures(&fn_item.params, &fn_item.body);
+
+        self.fn_scopes.push(FnScope::new(mod_index));
+        self.params(&fn_item.params);
+        self.block(&fn_item.body)?;
+        self.instr(Instr::Return);
+
+        let fn_scope = self.fn_scopes.pop().unwrap();
+
+        Ok(Fn::new_chunk(
+            self.arena,
+            self.mod_scopes[mod_index].modu.clone(),
+            Ident::new_string(self.arena, fn_item.ident.name.clone()),
+            arity,
+            fn_scope.chunk.build(self.arena),
+        ))
+    }
+
+    fn closure(
+        &mut self,
+        params: &[ast::Ident],
+        body: &ast::Block,
+        span: Span,
+    ) -> Result<()> {
+        let arity = u8::try_from(params.len())
+            .map_err(|_| Error::TooManyParams { span })?;
+        let mod_index = self.fn_scope().mod_index;
+
+        self.fn_scopes.push(FnScope::new(mod_index));
+        self.params(params);
+        self.block(body)?;
+        self.instr(Instr::Return);
+
+        let fn_scope = self.fn_scopes.pop().unwrap();
+
+        let fun = Fn::new_chunk(
+            self.arena,
+            self.mod_scopes[mod_index].modu.clone(),
+            Ident::new_string(self.arena, "<closure>".to_string()),
+            arity,
+            fn_scope.chunk.build(self.arena),
+        );
+        self.instr(Instr::PushFn { fun });
+        self.instr(Instr::MakeClosure);
+        Ok(())
+    }
+
+    /// Declares the parameters of a function. Arguments are placed in the
+    /// first local variables, in order, so captured parameters are copied
+    /// into the captured scope.
+    fn params(&mut self, params: &[ast::Ident]) {
+        for param in params {
+            let local = self.chunk().next_var();
+            if self.captures.vars.contains(&param.span) {
+                let var = self.captured_var();
+                self.instr(Instr::PushVar { var: local });
+                self.pop_var(var);
+                self.bind_var(&param.name, var);
+            } else {
+                self.bind_var(&param.name, Var::Local(local));
+            }
+        }
+    }
+
+    fn block(&mut self, block: &ast::Block) -> Result<()> {
+        self.fn_scope().blocks.push(Vec::new());
+
+        for stmt in &block.stmts {
+            self.stmt(stmt)?;
+        }
+        match &block.expr {
+            Some(expr) => self.expr(expr)?,
+            None => self.push_unit(),
+        }
+
+        self.fn_scope().blocks.pop();
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &ast::Stmt) -> Result<()> {
+        self.chunk().set_line(Some(stmt.span.line()));
+
+        match &stmt.kind {
+            StmtKind::Let { ident, value } => {
+                // The value is generated before the binding is declared, so
+                // that `let x = x + 1;` refers to the shadowed `x`.
+                self.expr(value)?;
+                let var = self.declare_var(ident);
+                self.pop_var(var);
+            }
+            StmtKind::Expr(expr) => {
+                self.expr(expr)?;
+                self.instr(Instr::Pop);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Generates instructions that leave exactly one value on the operand
+    /// stack.
+    fn expr(&mut self, expr: &ast::Expr) -> Result<()> {
+        // Instructions emitted after a nested expression, such as the `Call`
+        // after the arguments, belong to the line of this expression.
+        let line = self.chunk().line();
+        self.chunk().set_line(Some(expr.span.line()));
+        let result = self.expr_kind(expr);
+        self.chunk().set_line(line);
+        result
+    }
+
+    fn expr_kind(&mut self, expr: &ast::Expr) -> Result<()> {
+        match &expr.kind {
+            ExprKind::Literal(literal) => self.literal(literal),
+            ExprKind::Path(path) => self.path(path)?,
+            ExprKind::Block(block) => self.block(block)?,
+            ExprKind::Binary { op, left, right } => {
+                self.binary(*op, left, right)?
+            }
+            ExprKind::Unary { op, expr } => {
+                self.expr(expr)?;
+                self.instr(match op {
+                    UnaryOp::Negate => Instr::Negate,
+                    UnaryOp::Not => Instr::Not,
+                });
+            }
+            ExprKind::Assign { target, value } => self.assign(target, value)?,
+            ExprKind::Call { callee, args } => self.call(callee, args)?,
+
+            ExprKind::Tuple(exprs) => self.tuple(exprs, expr.span)?,
+            ExprKind::Struct { path, fields } => {
+                self.struct_expr(path, fields)?
+            }
+            ExprKind::List(exprs) => {
+                for expr in exprs {
+                    self.expr(expr)?;
+                }
+                self.instr(Instr::MakeList { len: exprs.len() });
+            }
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.expr(key)?;
+                    self.expr(value)?;
+                }
+                self.instr(Instr::MakeMap { len: entries.len() });
+            }
+            ExprKind::MethodCall {
+                receiver,
+                ident,
+                args,
+            } => self.method_call(receiver, ident, args)?,
+            ExprKind::Field { expr, field } => {
+                self.expr(expr)?;
+                let field = self.field(field);
+                self.instr(Instr::GetField { field });
+            }
+            ExprKind::Index { expr, index } => {
+                self.expr(expr)?;
+                self.expr(index)?;
+                self.instr(Instr::Index);
+            }
+            ExprKind::If {
+                cond,
+                then_block,
+                else_expr,
+            } => self.if_expr(cond, then_block, else_expr.as_deref())?,
+            ExprKind::While { cond, body } => self.while_expr(cond, body)?,
+            ExprKind::Closure { params, body } => {
+                self.closure(params, body, expr.span)?
+            }
+            ExprKind::Return(value) => {
+                match value {
+                    Some(value) => self.expr(value)?,
+                    None => self.push_unit(),
+                }
+                self.instr(Instr::Return);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn literal(&mut self, literal: &ast::Literal) {
+        let value = match literal {
+            ast::Literal::Boolean(boolean) => Value::Boolean(*boolean),
+            ast::Literal::Integer(integer) => Value::Integer(*integer),
+            ast::Literal::Float(float) => Value::Float(*float),
+            ast::Literal::String(string) => {
+                Value::String(value::String::new(self.arena, string.clone()))
+            }
+        };
+
+        self.instr(Instr::PushValue { value });
+    }
+
+    fn path(&mut self, path: &ast::Path) -> Result<()> {
+        if let [ident] = path.segments.as_slice() {
+            if let Some(var) = self.resolve_var(ident)? {
+                self.push_var(var);
+                return Ok(());
+            }
+        }
+
+        if let Some(ctor) = self.resolve_ctor(path)? {
+            self.make(ctor, Fields::Tuple(0));
+            return Ok(());
+        }
+
+        let modu = self.resolve_fn(path)?;
+        let ident = path.segments.last().unwrap();
+
+        self.instr(Instr::PushMod { modu });
+        self.instr(Instr::GetFn {
+            ident: Ident::new_string(self.arena, ident.name.clone()),
+        });
+        Ok(())
+    }
+
+    fn binary(
+        &mut self,
+        op: BinaryOp,
+        left: &ast::Expr,
+        right: &ast::Expr,
+    ) -> Result<()> {
+        match op {
+            BinaryOp::And => return self.short_circuit(left, right, false),
+            BinaryOp::Or => return self.short_circuit(left, right, true),
+            _ => {}
+        }
+
+        let instr = match op {
+            BinaryOp::Add => Instr::Add,
+            BinaryOp::Subtract => Instr::Subtract,
+            BinaryOp::Multiply => Instr::Multiply,
+            BinaryOp::Divide => Instr::Divide,
+            BinaryOp::Remainder => Instr::Remainder,
+            BinaryOp::Power => Instr::Power,
+            BinaryOp::BitAnd => Instr::BitAnd,
+            BinaryOp::BitOr => Instr::BitOr,
+            BinaryOp::BitXor => Instr::BitXor,
+            BinaryOp::ShiftLeft => Instr::ShiftLeft,
+            BinaryOp::ShiftRight => Instr::ShiftRight,
+            BinaryOp::Equal => Instr::Equal,
+            BinaryOp::NotEqual => Instr::NotEqual,
+            BinaryOp::Less => Instr::Less,
+            BinaryOp::LessEqual => Instr::LessEqual,
+            BinaryOp::Greater => Instr::Greater,
+            BinaryOp::GreaterEqual => Instr::GreaterEqual,
+            BinaryOp::And | BinaryOp::Or => unreachable!(),
+        };
+
+        self.expr(left)?;
+        self.expr(right)?;
+        self.instr(instr);
+        Ok(())
+    }
+
+    /// Generates `left && right` if `value` is `false`, or `left || right`
+    /// if it is `true`. The right operand is skipped if the left one is
+    /// `value`.
+    fn short_circuit(
+        &mut self,
+        left: &ast::Expr,
+        right: &ast::Expr,
+        value: bool,
+    ) -> Result<()> {
+        let short_label = self.label();
+        let end_label = self.label();
+
+        self.expr(left)?;
+        if value {
+            self.chunk().jump_if_true(short_label);
+        } else {
+            self.chunk().jump_if_false(short_label);
+        }
+        self.expr(right)?;
+        self.chunk().jump(end_label);
+
+        self.chunk().set_label(short_label);
+        self.instr(Instr::PushValue {
+            value: Value::Boolean(value),
+        });
+        self.chunk().set_label(end_label);
+        Ok(())
+    }
+
+    fn if_expr(
+        &mut self,
+        cond: &ast::Expr,
+        then_block: &ast::Block,
+        else_expr: Option<&ast::Expr>,
+    ) -> Result<()> {
+        let else_label = self.label();
+        let end_label = self.label();
+
+        self.expr(cond)?;
+        self.chunk().jump_if_false(else_label);
+        self.block(then_block)?;
+        self.chunk().jump(end_label);
+
+        self.chunk().set_label(else_label);
+        match else_expr {
+            Some(else_expr) => self.expr(else_expr)?,
+            None => self.push_unit(),
+        }
+        self.chunk().set_label(end_label);
+        Ok(())
+    }
+
+    /// Captured variables declared in the body live in a captured scope that
+    /// is entered afresh on every iteration, so closures created by different
+    /// iterations each keep their own.
+    fn while_expr(
+        &mut self,
+        cond: &ast::Expr,
+        body: &ast::Block,
+    ) -> Result<()> {
+        let start_label = self.label();
+        let end_label = self.label();
+
+        self.chunk().set_label(start_label);
+        self.expr(cond)?;
+        self.chunk().jump_if_false(end_label);
+        let captured_len = self.captures.loop_bodies.get(&body.span).copied();
+        if let Some(len) = captured_len {
+            self.instr(Instr::PushCapturedScope { len });
+            self.fn_scope().loop_scopes.push(0);
+        }
+        self.block(body)?;
+        if captured_len.is_some() {
+            self.fn_scope().loop_scopes.pop();
+            self.instr(Instr::PopCapturedScope);
+        }
+        self.instr(Instr::Pop);
+        self.chunk().jump(start_label);
+
+        self.chunk().set_label(end_label);
+        self.push_unit();
+        Ok(())
+    }
+
+    fn assign(&mut self, target: &ast::Expr, value: &ast::Expr) -> Result<()> {
+        match &target.kind {
+            ExprKind::Path(path) => {
+                let var = match path.segments.as_slice() {
+                    [ident] => self.resolve_var(ident)?,
+                    _ => None,
+                };
+                let var = var.ok_or_else(|| Error::UnresolvedName {
+                    name: path_name(path),
+                    span: path.span,
+                })?;
+
+                self.expr(value)?;
+                self.pop_var(var);
+                self.push_unit();
+                Ok(())
+            }
+            ExprKind::Field { expr, field } => {
+                self.expr(expr)?;
+                self.expr(value)?;
+                let field = self.field(field);
+                self.instr(Instr::SetField { field });
+                self.push_unit();
+                Ok(())
+            }
+            ExprKind::Index { expr, index } => {
+                self.expr(expr)?;
+                self.expr(index)?;
+                self.expr(value)?;
+                self.instr(Instr::SetIndex);
+                self.push_unit();
+                Ok(())
+            }
+            _ => Err(Error::InvalidAssignTarget { span: target.span }),
+        }
+    }
+
+    fn field(&self, field: &ast::Field) -> Ident<'gc> {
+        match field {
+            ast::Field::Named(ident) => {
+                Ident::new_string(self.arena, ident.name.clone())
+            }
+            ast::Field::Positional(index, _) => Ident::new_number(*index),
+        }
+    }
+
+    fn tuple(&mut self, exprs: &[ast::Expr], span: Span) -> Result<()> {
+        if exprs.is_empty() {
+            self.push_unit();
+            return Ok(());
+        }
+
+        let len = u8::try_from(exprs.len())
+            .map_err(|_| Error::TooManyFields { span })?;
+
+        for expr in exprs {
+            self.expr(expr)?;
+        }
+        self.instr(Instr::MakeTuple { len });
+        Ok(())
+    }
+
+    fn struct_expr(
+        &mut self,
+        path: &ast::Path,
+        fields: &[(ast::Ident, ast::Expr)],
+    ) -> Result<()> {
+        let ctor =
+            self.resolve_ctor(path)?
+                .ok_or_else(|| Error::UnresolvedName {
+                    name: path_name(path),
+                    span: path.span,
+                })?;
+        check_unique(fields.iter().map(|(ident, _)| ident))?;
+
+        let mut idents = Vec::new();
+        for (ident, value) in fields {
+            self.expr(value)?;
+            idents.push(Ident::new_string(self.arena, ident.name.clone()));
+        }
+        self.make(ctor, Fields::Map(idents));
+        Ok(())
+    }
+
+    fn make(&mut self, ctor: Ctor<'gc>, fields: Fields<'gc>) {
+        self.instr(match ctor {
+            Ctor::Struct(typ) => Instr::MakeStruct { typ, fields },
+            Ctor::Variant(typ, variant) => Instr::MakeEnum {
+                typ,
+                variant,
+                fields,
+            },
+        });
+    }
+
+    fn method_call(
+        &mut self,
+        receiver: &ast::Expr,
+        ident: &ast::Ident,
+        args: &[ast::Expr],
+    ) -> Result<()> {
+        let arity = u8::try_from(args.len())
+            .map_err(|_| Error::TooManyArgs { span: ident.span })?;
+
+        self.expr(receiver)?;
+        for arg in args {
+            self.expr(arg)?;
+        }
+        self.instr(Instr::CallMethod {
+            ident: Ident::new_string(self.arena, ident.name.clone()),
+            arity,
+        });
+        Ok(())
+    }
+
+    fn call(&mut self, callee: &ast::Expr, args: &[ast::Expr]) -> Result<()> {
+        if let ExprKind::Path(path) = &callee.kind {
+            if let Some(ctor) = self.resolve_ctor(path)? {
+                let len = u8::try_from(args.len())
+                    .map_err(|_| Error::TooManyFields { span: callee.span })?;
+                for arg in args {
+                    self.expr(arg)?;
+                }
+                self.make(ctor, Fields::Tuple(len));
+                return Ok(());
+            }
+        }
+
+        let arity = u8::try_from(args.len())
+            .map_err(|_| Error::TooManyArgs { span: callee.span })?;
+
+        self.expr(callee)?;
+        for arg in args {
+            self.expr(arg)?;
+        }
+        self.instr(Instr::Call {
+            arity,
+            is_method: false,
+        });
+        Ok(())
+    }
+
+    /// Resolves the module containing the function named by `path`.
+    fn resolve_fn(&self, path: &ast::Path) -> Result<Mod<'gc>> {
+        let mod_index = self
+            .resolve_item(&path.segments, |mod_scope, name| {
+                mod_scope.fns.contains_key(name)
+            })
+            .ok_or_else(|| Error::UnresolvedName {
+                name: path_name(path),
+                span: path.span,
+            })?;
+        Ok(self.mod_scopes[mod_index].modu.clone())
+    }
+
+    /// Resolves the struct, or the enum variant, named by `path`, unless it
+    /// names a variable. Variants are named by their enum followed by the
+    /// variant, as in `Option::Some`.
+    fn resolve_ctor(&self, path: &ast::Path) -> Result<Option<Ctor<'gc>>> {
+        if let [ident] = path.segments.as_slice() {
+            if self.resolve_var(ident)?.is_some() {
+                return Ok(None);
+            }
+        }
+
+        if let Some(Type::Struct(typ)) = self.resolve_type(&path.segments) {
+            return Ok(Some(Ctor::Struct(typ)));
+        }
+
+        let (variant, enum_segments) = path.segments.split_last().unwrap();
+        if enum_segments.is_empty() {
+            return Ok(None);
+        }
+        let ctor = match self.resolve_type(enum_segments) {
+            Some(Type::Enum(typ)) => typ
+                .variant_index(&Ident::new_string(
+                    self.arena,
+                    variant.name.clone(),
+                ))
+                .map(|index| Ctor::Variant(typ, index)),
+            _ => None,
+        };
+        Ok(ctor)
+    }
+
+    fn resolve_type(&self, segments: &[ast::Ident]) -> Option<Type<'gc>> {
+        let mod_index = self.resolve_item(segments, |mod_scope, name| {
+            mod_scope.types.contains_key(name)
+        })?;
+        let name = &segments.last().unwrap().name;
+        Some(self.mod_scopes[mod_index].types[name].0.clone())
+    }
+
+    /// Resolves the module containing the item named by the last of
+    /// `segments`, using `has_item` to tell whether a module contains it.
+    ///
+    /// The first segment is looked up in the current module and then in each
+    /// of its parents, while the remaining segments must be child modules.
+    fn resolve_item<F>(
+        &self,
+        segments: &[ast::Ident],
+        mut has_item: F,
+    ) -> Option<usize>
+    where
+        F: FnMut(&ModScope<'gc>, &str) -> bool,
+    {
+        let (ident, mod_idents) = segments.split_last().unwrap();
+        let mut mod_index = self.fn_scopes.last().unwrap().mod_index;
+
+        let mut mod_idents = mod_idents.iter();
+        if let Some(first_ident) = mod_idents.next() {
+            mod_index = self.find_in_ancestors(mod_index, |index| {
+                self.mod_scopes[index]
+                    .child_mods
+                    .get(&first_ident.name)
+                    .cloned()
+            })?;
+
+            for mod_ident in mod_idents {
+                mod_index = *self.mod_scopes[mod_index]
+                    .child_mods
+                    .get(&mod_ident.name)?;
+            }
+
+            if has_item(&self.mod_scopes[mod_index], &ident.name) {
+                Some(mod_index)
+            } else {
+                None
+            }
+        } else {
+            self.find_in_ancestors(mod_index, |index| {
+                if has_item(&self.mod_scopes[index], &ident.name) {
+                    Some(index)
+                } else {
+                    None
+                }
+            })
+        }
+    }
+
+    /// Calls `f` with `mod_index` and then the index of each of its parents,
+    /// from the innermost outwards, until it returns `Some`.
+    fn find_in_ancestors<T, F>(&self, mod_index: usize, mut f: F) -> Option<T>
+    where
+        F: FnMut(usize) -> Option<T>,
+    {
+        let mut next_index = Some(mod_index);
+        while let Some(index) = next_index {
+            if let Some(found) = f(index) {
+                return Some(found);
+            }
+            next_index = self.mod_scopes[index].parent;
+        }
+        None
+    }
+
+    fn declare_var(&mut self, ident: &ast::Ident) -> Var {
+        let var = if self.captures.vars.contains(&ident.span) {
+            self.captured_var()
+        } else {
+            Var::Local(self.chunk().next_var())
+        };
+        self.bind_var(&ident.name, var);
+        var
+    }
+
+    /// Allocates a variable in the innermost captured scope of the current
+    /// function.
+    fn captured_var(&mut self) -> Var {
+        let fn_scope = self.fn_scope();
+        let var = match fn_scope.loop_scopes.last_mut() {
+            Some(len) => {
+                *len += 1;
+                *len - 1
+            }
+            None => fn_scope.chunk.next_captured_var(),
+        };
+        Var::Captured { parents: 0, var }
+    }
+
+    fn bind_var(&mut self, name: &str, var: Var) {
+        let fn_scope = self.fn_scope();
+        let binding = match var {
+            Var::Local(var) => Binding::Local(var),
+            Var::Captured { var, .. } => Binding::Captured {
+                scope: fn_scope.loop_scopes.len(),
+                var,
+            },
+        };
+        fn_scope
+            .blocks
+            .last_mut()
+            .unwrap()
+            .push((name.to_string(), binding));
+    }
+
+    /// Resolves `name` in the current function, and then in each enclosing
+    /// one if it is a closure.
+    fn resolve_var(&self, ident: &ast::Ident) -> Result<Option<Var>> {
+        // How many parents the innermost captured scope of the function being
+        // searched is from the current one.
+        let mut outer_scopes = 0;
+        for (depth, fn_scope) in self.fn_scopes.iter().rev().enumerate() {
+            let found = fn_scope
+                .blocks
+                .iter()
+                .rev()
+                .flat_map(|block| block.iter().rev())
+                .find(|(var_name, _)| *var_name == ident.name);
+
+            match found {
+                Some(&(_, Binding::Captured { scope, var })) => {
+                    let parents =
+                        outer_scopes + fn_scope.loop_scopes.len() - scope;
+                    return Ok(Some(Var::Captured { parents, var }));
+                }
+                Some(&(_, Binding::Local(var))) if depth == 0 => {
+                    return Ok(Some(Var::Local(var)));
+                }
+                Some(_) => {
+                    return Err(Error::UncapturedVar {
+                        name: ident.name.clone(),
+                        span: ident.span,
+                    });
+                }
+                None => outer_scopes += fn_scope.loop_scopes.len() + 1,
+            }
+        }
+        Ok(None)
+    }
+
+    fn push_var(&mut self, var: Var) {
+        self.instr(match var {
+            Var::Local(var) => Instr::PushVar { var },
+            Var::Captured { parents, var } => {
+                Instr::PushCapturedVar { parents, var }
+            }
+        });
+    }
+
+    fn pop_var(&mut self, var: Var) {
+        self.instr(match var {
+            Var::Local(var) => Instr::PopVar { var },
+            Var::Captured { parents, var } => {
+                Instr::PopCapturedVar { parents, var }
+            }
+        });
+    }
+
+    fn push_unit(&mut self) {
+        self.instr(Instr::PushValue { value: Value::Unit });
+    }
+
+    fn instr(&mut self, instr: Instr<'gc>) {
+        self.chunk().instr(instr);
+    }
+
+    fn label(&mut self) -> Label {
+        self.chunk().label()
+    }
+
+    fn chunk(&mut self) -> &mut ChunkBuilder<'gc> {
+        &mut self.fn_scope().chunk
+    }
+
+    fn fn_scope(&mut self) -> &mut FnScope<'gc> {
+        self.fn_scopes.last_mut().unwrap()
+    }
+}
+
+struct ModScope<'gc> {
+    modu: Mod<'gc>,
+    parent: Option<usize>,
+    span: Span,
+    child_mods: BTreeMap<String, usize>,
+    fns: BTreeMap<String, Span>,
+    types: BTreeMap<String, (Type<'gc>, Span)>,
+}
+
+impl<'gc> ModScope<'gc> {
+    fn new(modu: Mod<'gc>, parent: Option<usize>, span: Span) -> ModScope<'gc> {
+        ModScope {
+            modu,
+            parent,
+            span,
+            child_mods: BTreeMap::new(),
+            fns: BTreeMap::new(),
+            types: BTreeMap::new(),
+        }
+    }
+}
+
+struct FnScope<'gc> {
+    mod_index: usize,
+    chunk: ChunkBuilder<'gc>,
+    // The variables declared in each enclosing block, innermost last. Later
+    // declarations shadow earlier ones.
+    blocks: Vec<Vec<(String, Binding)>>,
+    // The number of variables in each captured scope entered for a loop
+    // body, innermost last.
+    loop_scopes: Vec<usize>,
+}
+
+/// What constructs a struct or enum value.
+enum Ctor<'gc> {
+    Struct(typ::Struct<'gc>),
+    Variant(typ::Enum<'gc>, u8),
+}
+
+/// Where a variable lives. Captured variables are found `parents` closures
+/// out from the one using them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Var {
+    Local(usize),
+    Captured { parents: usize, var: usize },
+}
+
+/// What a name is bound to. Captured variables are in the captured scope
+/// `scope` loop bodies deep in the function declaring them.
+#[derive(Clone, Copy, Debug)]
+enum Binding {
+    Local(usize),
+    Captured { scope: usize, var: usize },
+}
+
+impl<'gc> FnScope<'gc> {
+    fn new(mod_index: usize) -> FnScope<'gc> {
+        FnScope {
+            mod_index,
+            chunk: ChunkBuilder::new(),
+            blocks: vec![Vec::new()],
+            loop_scopes: Vec::new(),
+        }
+    }
+}
+
+fn path_name(path: &ast::Path) -> String {
+    path.segments
+        .iter()
+        .map(|ident| ident.name.as_str())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// Fails if any two of `idents`, such as the fields of a struct, have the
+/// same name.
+fn check_unique<'a, I>(idents: I) -> Result<()>
+where
+    I: IntoIterator<Item = &'a ast::Ident>,
+{
+    let mut spans = BTreeMap::new();
+    for ident in idents {
+        if let Some(prev_span) = spans.insert(&ident.name, ident.span) {
+            return Err(Error::DuplicateItem {
+                name: ident.name.clone(),
+                span: ident.span,
+                prev_span,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use eko_gc::Arena;
+
+    use crate::compiler::error::Error;
+    use crate::compiler::parser::Parser;
+    use crate::core::fun::{External, Fn, FnProto};
+    use crate::core::ident::Ident;
+    use crate::core::instr::Instr;
+    use crate::core::modu::Mod;
+    use crate::core::typ::{StructProto, Type};
+    use crate::core::value::{self, Tuple, Value};
+    use crate::engine::error::{CallError, Error as EngineError};
+    use crate::engine::machine::Machine;
+
+    use super::Generator;
+
+    fn generate<'gc>(
+        arena: &Arena<'gc>,
+        source: &str,
+    ) -> Result<Mod<'gc>, Error> {
+        let (file, diagnostics) = Parser::parse(source);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let modu = Mod::new(arena, Ident::new_number(0));
+        Generator::new(arena).generate(modu.clone(), &file)?;
+        Ok(modu)
+    }
+
+    fn run<'gc>(arena: &Arena<'gc>, source: &str) -> Value<'gc> {
+        let modu = generate(arena, source).unwrap();
+        let main = modu
+            .fun(&Ident::new_string(arena, "main".to_string()))
+            .unwrap();
+
+        let mut machine = Machine::new(arena);
+        machine.push_fn(main);
+        machine.call(0, false).unwrap();
+        machine.pop().unwrap()
+    }
+
+    #[test]
+    fn let_bindings() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn main() {
+                 let x = 2;
+                 let y = x * 3;
+                 y - x
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(4));
+    }
+
+    #[test]
+    fn shadowing_and_blocks() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn main() {
+                 let x = 1;
+                 let y = {
+                     let x = x + 10;
+                     x * 2
+                 };
+                 let x = x + 100;
+                 x + y
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(123));
+    }
+
+    #[test]
+    fn assignment() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn main() {
+                 let x = 1;
+                 { x = x + 1; }
+                 x = x * 5;
+                 x
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(10));
+    }
+
+    #[test]
+    fn closures() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn counter(start) {
+                 let count = start;
+                 fn() { count = count + 1; count }
+             }
+             fn adder(n) { fn(x) { fn(y) { x + y + n } } }
+             fn apply(f, x) { f(x) }
+             fn main() {
+                 let next = counter(10);
+                 next();
+                 let second = next();
+                 let add = adder(100)(20);
+                 let outer = 1;
+                 let double = fn() { outer = outer * 2; };
+                 double();
+                 double();
+                 apply(fn(x) { x * 3 }, second) + add(3) + outer
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(36 + 123 + 4));
+    }
+
+    #[test]
+    fn loop_captures() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn main() {
+                 let fns = [];
+                 let i = 0;
+                 while i < 3 {
+                     let n = i;
+                     fns.push(fn() { n });
+                     i = i + 1;
+                 }
+                 fns[0]() * 10 + fns[2]()
+             }",
+        );
+
+        // Each closure keeps its own `n`.
+        assert_eq!(value, Value::Integer(2));
+
+        let value = run(
+            &arena,
+            "fn main() {
+                 let base = 100;
+                 let fns = [];
+                 let i = 0;
+                 while i < 2 {
+                     let j = 0;
+                     while j < 2 {
+                         let n = i * 10 + j;
+                         fns.push(fn() { fn() { base + n } });
+                         j = j + 1;
+                     }
+                     let m = i;
+                     fns.push(fn() { m });
+                     i = i + 1;
+                 }
+                 fns[0]()() + fns[5]() * 1000 + fns[4]()() * 10000
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(100 + 1000 + 111 * 10000));
+    }
+
+    #[test]
+    fn fn_values() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn double(x) { x * 2 }
+             fn compose(f, g) { fn(x) { g(f(x)) } }
+             fn pick(first) { if first { double } else { math::square } }
+             mod math { fn square(x) { x * x } }
+             fn main() {
+                 let f = compose(double, pick(false));
+                 let same = if double == double { 1 } else { 0 };
+                 f(3) + pick(true)(1) + same
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(36 + 2 + 1));
+    }
+
+    #[test]
+    fn external_fn_values() {
+        let arena = Arena::new();
+
+        let modu = generate(&arena, "fn apply(f, x) { f(x) }").unwrap();
+        let apply = modu
+            .fun(&Ident::new_string(&arena, "apply".to_string()))
+            .unwrap();
+        let double = Fn::new_external(
+            &arena,
+            modu,
+            Ident::new_string(&arena, "double".to_string()),
+            1,
+            External::new(&arena, |args| match args[0] {
+                Value::Integer(integer) => Value::Integer(integer * 2),
+                _ => panic!("unexpected args: {:?}", args),
+            }),
+        );
+
+        let mut machine = Machine::new(&arena);
+        machine.push_fn(apply);
+        machine.push_value(Value::Fn(double));
+        machine.push_value(Value::Integer(21));
+        machine.call(2, false).unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(42));
+    }
+
+    #[test]
+    fn fields() {
+        let arena = Arena::new();
+
+        let modu = generate(
+            &arena,
+            "fn swap(t) {
+                 let first = t.0;
+                 t.0 = t.1;
+                 t.1 = first;
+                 t.0
+             }",
+        )
+        .unwrap();
+        let swap = modu
+            .fun(&Ident::new_string(&arena, "swap".to_string()))
+            .unwrap();
+        let tuple =
+            Tuple::new(&arena, vec![Value::Integer(1), Value::Integer(2)]);
+
+        let mut machine = Machine::new(&arena);
+        machine.push_fn(swap.clone());
+        machine.push_value(Value::Tuple(tuple.clone()));
+        machine.call(1, false).unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(2));
+        assert_eq!(tuple.field(0).unwrap(), Value::Integer(2));
+        assert_eq!(tuple.field(1).unwrap(), Value::Integer(1));
+
+        let tuple = Tuple::new(&arena, vec![Value::Integer(1)]);
+        machine.push_fn(swap);
+        machine.push_value(Value::Tuple(tuple));
+        match machine.call(1, false).map_err(CallError::into_error) {
+            Err(EngineError::InvalidField {
+                field: Ident::Number(1),
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn unit() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn main() {
+                 let x = empty();
+                 x == () && statements() == ()
+             }
+             fn empty() {}
+             fn statements() { let y = 1; y + 1; }",
+        );
+        assert_eq!(value, Value::Boolean(true));
+
+        assert_eq!(run(&arena, "fn main() { while false {} }"), Value::Unit);
+    }
+
+    #[test]
+    fn tuples() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn main() {
+                 let t = (1, (2, 3));
+                 let u = t.1;
+                 t.0 + u.1
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(4));
+    }
+
+    #[test]
+    fn lists() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn main() {
+                 let xs = [];
+                 let i = 0;
+                 while i < 5 {
+                     xs.push(i * i);
+                     i = i + 1;
+                 }
+                 xs[0] = xs.pop();
+                 let sum = 0;
+                 let ys = xs.slice(1, xs.len());
+                 i = 0;
+                 while i < ys.len() {
+                     sum = sum + ys[i];
+                     i = i + 1;
+                 }
+                 let result = (xs[0], sum);
+                 result
+             }",
+        );
+
+        match value {
+            Value::Tuple(tuple) => {
+                assert_eq!(tuple.field(0).unwrap(), Value::Integer(16));
+                assert_eq!(tuple.field(1).unwrap(), Value::Integer(14));
+            }
+            value => panic!("unexpected value: {:?}", value),
+        }
+    }
+
+    #[test]
+    fn maps() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn main() {
+                 let counts = [:];
+                 let words = [\"a\", \"b\", \"a\"];
+                 let i = 0;
+                 while i < words.len() {
+                     let word = words[i];
+                     if counts.contains(word) {
+                         counts[word] = counts[word] + 1;
+                     } else {
+                         counts.insert(word, 1);
+                     }
+                     i = i + 1;
+                 }
+                 let totals = [(1, 2): counts[\"a\"], \"b\": counts[\"b\"]];
+                 totals.remove((1, 2)) * 10 + totals.keys().len()
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(21));
+    }
+
+    #[test]
+    fn strings() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn main() {
+                 let greeting = \"héllo\" + \", \" + \"wörld\";
+                 let words = greeting.split(\", \");
+                 let first = words[1].to_uppercase();
+                 if greeting.find(\"w\") == 7 {
+                     first[1] + first.slice(3, 5)
+                 } else {
+                     greeting
+                 }
+             }",
+        );
+
+        assert_eq!(
+            value,
+            Value::String(value::String::from_str(&arena, "ÖLD"))
+        );
+    }
+
+    #[test]
+    fn calls() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn main() { math::sub(10, twice(3)) }
+             fn twice(x) { x * 2 }
+             mod math {
+                 fn sub(a, b) { a - b }
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(4));
+    }
+
+    #[test]
+    fn nested_mods() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn one() { 1 }
+             fn main() { a::b::two() + a::three() }
+             mod a {
+                 fn three() { b::two() + one() }
+                 mod b {
+                     fn two() { one() + one() }
+                 }
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(5));
+    }
+
+    #[test]
+    fn control_flow() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn pick(a, b) {
+                 if a { 1 } else if b { 2 } else { 3 }
+             }
+             fn main() {
+                 let again = true;
+                 let n = 0;
+                 while again || false {
+                     n = n + pick(false, true);
+                     again = false;
+                 }
+                 n * 100 + pick(false, false) * 10 + pick(false, true)
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(232));
+    }
+
+    #[test]
+    fn short_circuit() {
+        let arena = Arena::new();
+
+        // `one` is called with the wrong arity, which would fail if the right
+        // operand were evaluated.
+        let value = run(
+            &arena,
+            "fn one(x) { x }
+             fn main() {
+                 if false && one() { 1 } else if true || one() { 2 } else { 3 }
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(2));
+    }
+
+    #[test]
+    fn comparisons() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn fact(n) { if n <= 1 { 1 } else { n * fact(n - 1) } }
+             fn main() {
+                 let i = 0;
+                 let sum = 0;
+                 while !(i >= 5) {
+                     i = i + 1;
+                     if i != 3 && \"a\" < \"b\" { sum = sum + i; }
+                 }
+                 if fact(5) == 120.0 { -sum } else { 0 }
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(-12));
+    }
+
+    #[test]
+    fn operators() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn main() {
+                 let flags = 1 << 4 | 1 << 1;
+                 let checksum = (flags ^ 255) & 15;
+                 let low = (0 - flags).div_floor(4);
+                 checksum + 2 ** 3 * 10 % 7 + (flags >> 1) + low
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(13 + 3 + 9 - 5));
+    }
+
+    #[test]
+    fn returns() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "fn find(n) {
+                 let i = 0;
+                 while true {
+                     if i * i >= n { return i; }
+                     i = i + 1;
+                 }
+             }
+             fn nothing() { return; }
+             fn main() {
+                 nothing();
+                 find(50) + 10 * (1 + return 5)
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(5));
+    }
+
+    #[test]
+    fn lines() {
+        let arena = Arena::new();
+
+        let modu = generate(
+            &arena,
+            "fn main() {
+                 let x = 1;
+                 x < true
+             }",
+        )
+        .unwrap();
+        let main = modu
+            .fun(&Ident::new_string(&arena, "main".to_string()))
+            .unwrap();
+        let chunk = match main.proto() {
+            FnProto::Chunk(chunk) => chunk.clone(),
+            FnProto::External(_) => panic!("unexpected external"),
+        };
+
+        let less = (0..chunk.len())
+            .position(|index| matches!(chunk.instr(index), Some(Instr::Less)))
+            .unwrap();
+        assert_eq!(chunk.line(0), Some(2));
+        assert_eq!(chunk.line(less), Some(3));
+    }
+
+    #[test]
+    fn structs() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "struct Pair(first, second);
+             struct Unit;
+             fn main() {
+                 let pair = Pair(1, shapes::Size(3));
+                 pair.0 = pair.0 + 10;
+                 let same = Unit == Unit && Pair(1, 2) == Pair(1, 2);
+                 if same && Pair(11, shapes::Size(3)) == pair {
+                     pair.0 * 10 + pair.1.0
+                 } else {
+                     0
+                 }
+             }
+             mod shapes { struct Size(w); }",
+        );
+
+        assert_eq!(value, Value::Integer(113));
+    }
+
+    #[test]
+    fn enums() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "enum Shape { Empty, Circle(radius), Square(side) }
+             fn main() {
+                 let circle = Shape::Circle(2);
+                 let square = Shape::Square(3);
+                 square.0 = 4;
+                 let distinct = square != Shape::Empty
+                     && circle != Shape::Circle(3)
+                     && Shape::Square(2) != Shape::Circle(2)
+                     && Shape::Empty == Shape::Empty;
+                 if distinct { 3 * circle.0 * circle.0 + square.0 * square.0 }
+                 else { 0 }
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(12 + 16));
+    }
+
+    #[test]
+    fn struct_literals() {
+        let arena = Arena::new();
+
+        let value = run(
+            &arena,
+            "struct Point { x, y }
+             enum Shape { Rect { w, h } }
+             fn main() {
+                 let p = Point { y: 2, x: 1 };
+                 p.x = p.x + 10;
+                 let rect = Shape::Rect { w: p.x, h: p.y };
+                 if (Point { x: 11, y: 2 }) == p { rect.w * rect.h } else { 0 }
+             }",
+        );
+
+        assert_eq!(value, Value::Integer(22));
+    }
+
+    #[test]
+    fn types() {
+        let arena = Arena::new();
+        let ident = |name: &str| Ident::new_string(&arena, name.to_string());
+
+        let modu = generate(
+            &arena,
+            "struct Point { x, y }
+             mod shapes { enum Shape { Empty, Circle(radius) } }",
+        )
+        .unwrap();
+
+        match modu.typ(&ident("Point")) {
+            Some(Type::Struct(point)) => match &*point.proto() {
+                StructProto::Map(map_data) => {
+                    assert_eq!(map_data.fields().len(), 2)
+                }
+                proto => panic!("unexpected proto: {:?}", proto),
+            },
+            typ => panic!("unexpected type: {:?}", typ),
+        }
+
+        let shapes = modu.child_mod(&ident("shapes")).unwrap();
+        match shapes.typ(&ident("Shape")) {
+            Some(Type::Enum(shape)) => {
+                assert_eq!(shape.variant_index(&ident("Circle")), Some(1));
+                assert_eq!(shape.variant_index(&ident("Square")), None);
+            }
+            typ => panic!("unexpected type: {:?}", typ),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let arena = Arena::new();
+
+        match generate(&arena, "fn main() { x }") {
+            Err(Error::UnresolvedName { name, .. }) => assert_eq!(name, "x"),
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+
+        match generate(&arena, "fn main() { a::f() } mod a {}") {
+            Err(Error::UnresolvedName { name, .. }) => {
+                assert_eq!(name, "a::f")
+            }
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+
+        match generate(&arena, "fn main() {} fn main() {}") {
+            Err(Error::DuplicateItem { name, .. }) => assert_eq!(name, "main"),
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+
+        match generate(&arena, "fn main() { { let x = 1; } x }") {
+            Err(Error::UnresolvedName { name, .. }) => assert_eq!(name, "x"),
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+
+        match generate(&arena, "fn main() {} struct main;") {
+            Err(Error::DuplicateItem { name, .. }) => assert_eq!(name, "main"),
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+
+        match generate(&arena, "enum E { A, B(x), A { y } }") {
+            Err(Error::DuplicateItem { name, .. }) => assert_eq!(name, "A"),
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+
+        match generate(&arena, "enum E { A } fn main() { E::B }") {
+            Err(Error::UnresolvedName { name, .. }) => {
+                assert_eq!(name, "E::B")
+            }
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+
+        match generate(&arena, "struct P { x } fn main() { P { x: 1, x: 2 } }")
+        {
+            Err(Error::DuplicateItem { name, .. }) => assert_eq!(name, "x"),
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+
+        match generate(&arena, "struct P { x, y, x }") {
+            Err(Error::DuplicateItem { name, .. }) => assert_eq!(name, "x"),
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
     }
 }
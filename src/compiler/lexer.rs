@@ -0,0 +1,634 @@
+use std::fmt;
+
+use super::error::{Error, Result};
+use super::span::Span;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    kind: TokenKind,
+    span: Span,
+}
+
+impl Token {
+    pub fn new(kind: TokenKind, span: Span) -> Token {
+        Token { kind, span }
+    }
+
+    pub fn kind(&self) -> &TokenKind {
+        &self.kind
+    }
+
+    pub fn into_kind(self) -> TokenKind {
+        self.kind
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenKind {
+    Ident(String),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Boolean(bool),
+    Comment(String),
+
+    Fn,
+    Struct,
+    Enum,
+    Mod,
+    Let,
+    If,
+    Else,
+    While,
+    Return,
+
+    Plus,
+    Minus,
+    Star,
+    StarStar,
+    Slash,
+    Percent,
+    Bang,
+    Eq,
+    EqEq,
+    BangEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    AndAnd,
+    OrOr,
+    And,
+    Or,
+    Caret,
+    LtLt,
+    GtGt,
+
+    Dot,
+    Comma,
+    Colon,
+    ColonColon,
+    Semi,
+    OpenParen,
+    CloseParen,
+    OpenBrace,
+    CloseBrace,
+    OpenBracket,
+    CloseBracket,
+
+    Eof,
+}
+
+impl TokenKind {
+    fn keyword(ident: &str) -> Option<TokenKind> {
+        use self::TokenKind::*;
+
+        let kind = match ident {
+            "fn" => Fn,
+            "struct" => Struct,
+            "enum" => Enum,
+            "mod" => Mod,
+            "let" => Let,
+            "if" => If,
+            "else" => Else,
+            "while" => While,
+            "return" => Return,
+            "true" => Boolean(true),
+            "false" => Boolean(false),
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::TokenKind::*;
+
+        let symbol = match self {
+            Ident(ident) => return write!(f, "`{}`", ident),
+            Integer(integer) => return write!(f, "`{}`", integer),
+            Float(float) => return write!(f, "`{:?}`", float),
+            String(string) => return write!(f, "{:?}", string),
+            Boolean(boolean) => return write!(f, "`{}`", boolean),
+            Comment(_) => return write!(f, "comment"),
+
+            Fn => "fn",
+            Struct => "struct",
+            Enum => "enum",
+            Mod => "mod",
+            Let => "let",
+            If => "if",
+            Else => "else",
+            While => "while",
+            Return => "return",
+
+            Plus => "+",
+            Minus => "-",
+            Star => "*",
+            StarStar => "**",
+            Slash => "/",
+            Percent => "%",
+            Bang => "!",
+            Eq => "=",
+            EqEq => "==",
+            BangEq => "!=",
+            Lt => "<",
+            LtEq => "<=",
+            Gt => ">",
+            GtEq => ">=",
+            AndAnd => "&&",
+            OrOr => "||",
+            And => "&",
+            Or => "|",
+            Caret => "^",
+            LtLt => "<<",
+            GtGt => ">>",
+
+            Dot => ".",
+            Comma => ",",
+            Colon => ":",
+            ColonColon => "::",
+            Semi => ";",
+            OpenParen => "(",
+            CloseParen => ")",
+            OpenBrace => "{",
+            CloseBrace => "}",
+            OpenBracket => "[",
+            CloseBracket => "]",
+
+            Eof => return write!(f, "end of file"),
+        };
+        write!(f, "`{}`", symbol)
+    }
+}
+
+pub struct Lexer<'a> {
+    source: &'a str,
+    pos: usize,
+    line: usize,
+    column: usize,
+    // Whether the previous token was a `.`, in which case a number is lexed
+    // as an integer only, so that `tuple.0.1` is not read as `tuple.(0.1)`.
+    after_dot: bool,
+    errors: Vec<Error>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Lexer<'a> {
+        Lexer {
+            source,
+            pos: 0,
+            line: 1,
+            column: 1,
+            after_dot: false,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Lexes all of `source`, ending with a `TokenKind::Eof`, along with the
+    /// errors encountered on the way.
+    pub fn lex(source: &'a str) -> (Vec<Token>, Vec<Error>) {
+        let mut lexer = Lexer::new(source);
+        let mut tokens = Vec::new();
+
+        loop {
+            let token = lexer.next_token();
+            let is_eof = token.kind == TokenKind::Eof;
+            tokens.push(token);
+            if is_eof {
+                return (tokens, lexer.errors);
+            }
+        }
+    }
+
+    /// Lexes the next token, returning `TokenKind::Eof` once the source is
+    /// exhausted.
+    ///
+    /// Errors are recorded rather than returned. Unexpected characters are
+    /// skipped, while malformed literals still produce a token so that
+    /// parsing is not thrown off.
+    pub fn next_token(&mut self) -> Token {
+        loop {
+            if let Some(token) = self.try_next_token() {
+                return token;
+            }
+        }
+    }
+
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    fn try_next_token(&mut self) -> Option<Token> {
+        use self::TokenKind::*;
+
+        self.skip_trivia();
+
+        let start = self.pos;
+        let line = self.line;
+        let column = self.column;
+        let after_dot = self.after_dot;
+        self.after_dot = false;
+
+        let ch = match self.bump() {
+            Some(ch) => ch,
+            None => {
+                return Some(Token::new(
+                    Eof,
+                    self.span_from(start, line, column),
+                ));
+            }
+        };
+
+        let kind = match ch {
+            '+' => Plus,
+            '-' => Minus,
+            '*' => self.either('*', StarStar, Star),
+            '/' if self.peek() == Some('/') => {
+                while self.peek().is_some_and(|ch| ch != '\n') {
+                    self.bump();
+                }
+                Comment(self.source[start..self.pos].trim_end().to_string())
+            }
+            '/' => Slash,
+            '%' => Percent,
+            '^' => Caret,
+            '!' => self.either('=', BangEq, Bang),
+            '=' => self.either('=', EqEq, Eq),
+            '<' => {
+                if self.eat('=') {
+                    LtEq
+                } else {
+                    self.either('<', LtLt, Lt)
+                }
+            }
+            '>' => {
+                if self.eat('=') {
+                    GtEq
+                } else {
+                    self.either('>', GtGt, Gt)
+                }
+            }
+            '&' => self.either('&', AndAnd, And),
+            '|' => self.either('|', OrOr, Or),
+            ':' => self.either(':', ColonColon, Colon),
+            '.' => {
+                self.after_dot = true;
+                Dot
+            }
+            ',' => Comma,
+            ';' => Semi,
+            '(' => OpenParen,
+            ')' => CloseParen,
+            '{' => OpenBrace,
+            '}' => CloseBrace,
+            '[' => OpenBracket,
+            ']' => CloseBracket,
+            '"' => self.string(start, line, column),
+            '0'..='9' => self.number(start, line, column, after_dot),
+            ch if is_ident_start(ch) => {
+                while self.peek().is_some_and(is_ident_continue) {
+                    self.bump();
+                }
+                let ident = &self.source[start..self.pos];
+                TokenKind::keyword(ident)
+                    .unwrap_or_else(|| Ident(ident.to_string()))
+            }
+            ch => {
+                self.errors.push(Error::UnexpectedChar {
+                    ch,
+                    span: self.span_from(start, line, column),
+                });
+                return None;
+            }
+        };
+
+        Some(Token::new(kind, self.span_from(start, line, column)))
+    }
+
+    fn skip_trivia(&mut self) {
+        loop {
+            match self.peek() {
+                Some(ch) if ch.is_whitespace() => {
+                    self.bump();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn string(
+        &mut self,
+        start: usize,
+        line: usize,
+        column: usize,
+    ) -> TokenKind {
+        let mut string = String::new();
+
+        loop {
+            let escape_start = self.pos;
+            let escape_line = self.line;
+            let escape_column = self.column;
+
+            match self.bump() {
+                Some('"') => return TokenKind::String(string),
+                Some('\\') => {
+                    let escaped = match self.bump() {
+                        Some('n') => Ok('\n'),
+                        Some('r') => Ok('\r'),
+                        Some('t') => Ok('\t'),
+                        Some('0') => Ok('\0'),
+                        Some('\\') => Ok('\\'),
+                        Some('"') => Ok('"'),
+                        Some('u') => self.unicode_escape(
+                            escape_start,
+                            escape_line,
+                            escape_column,
+                        ),
+                        Some(ch) => Err(Error::InvalidEscape {
+                            escape: ch.to_string(),
+                            span: self.span_from(
+                                escape_start,
+                                escape_line,
+                                escape_column,
+                            ),
+                        }),
+                        None => break,
+                    };
+
+                    // An invalid escape is left out of the string, which is
+                    // still lexed up to its closing `"`.
+                    match escaped {
+                        Ok(ch) => string.push(ch),
+                        Err(error) => self.errors.push(error),
+                    }
+                }
+                Some(ch) => string.push(ch),
+                None => break,
+            }
+        }
+
+        self.errors.push(Error::UnterminatedString {
+            span: self.span_from(start, line, column),
+        });
+        TokenKind::String(string)
+    }
+
+    fn unicode_escape(
+        &mut self,
+        start: usize,
+        line: usize,
+        column: usize,
+    ) -> Result<char> {
+        if self.eat('{') {
+            let digits_start = self.pos;
+            while self.peek().is_some_and(|ch| ch.is_ascii_hexdigit()) {
+                self.bump();
+            }
+            let digits = &self.source[digits_start..self.pos];
+
+            if self.eat('}') {
+                if let Some(ch) = u32::from_str_radix(digits, 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                {
+                    return Ok(ch);
+                }
+            }
+        }
+
+        Err(Error::InvalidEscape {
+            escape: self.source[start + 1..self.pos].to_string(),
+            span: self.span_from(start, line, column),
+        })
+    }
+
+    fn number(
+        &mut self,
+        start: usize,
+        line: usize,
+        column: usize,
+        after_dot: bool,
+    ) -> TokenKind {
+        self.digits();
+
+        let is_float = !after_dot
+            && self.peek() == Some('.')
+            && self.peek_next().is_some_and(|ch| ch.is_ascii_digit());
+
+        if is_float {
+            self.bump();
+            self.digits();
+        }
+
+        let number: String = self.source[start..self.pos]
+            .chars()
+            .filter(|&ch| ch != '_')
+            .collect();
+
+        let kind = if is_float {
+            number.parse().ok().map(TokenKind::Float)
+        } else {
+            number.parse().ok().map(TokenKind::Integer)
+        };
+
+        kind.unwrap_or_else(|| {
+            self.errors.push(Error::InvalidNumber {
+                number: self.source[start..self.pos].to_string(),
+                span: self.span_from(start, line, column),
+            });
+            TokenKind::Integer(0)
+        })
+    }
+
+    fn digits(&mut self) {
+        while self
+            .peek()
+            .is_some_and(|ch| ch.is_ascii_digit() || ch == '_')
+        {
+            self.bump();
+        }
+    }
+
+    fn either(
+        &mut self,
+        expected: char,
+        matched: TokenKind,
+        unmatched: TokenKind,
+    ) -> TokenKind {
+        if self.eat(expected) {
+            matched
+        } else {
+            unmatched
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn peek_next(&self) -> Option<char> {
+        let mut chars = self.source[self.pos..].chars();
+        chars.next();
+        chars.next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.pos += ch.len_utf8();
+        if ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(ch)
+    }
+
+    fn span_from(&self, start: usize, line: usize, column: usize) -> Span {
+        Span::new(start, self.pos, line, column)
+    }
+}
+
+fn is_ident_start(ch: char) -> bool {
+    ch == '_' || ch.is_alphabetic()
+}
+
+fn is_ident_continue(ch: char) -> bool {
+    ch == '_' || ch.is_alphanumeric()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::error::Error;
+    use crate::compiler::span::Span;
+
+    use super::TokenKind::*;
+    use super::{Lexer, TokenKind};
+
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        let (tokens, errors) = Lexer::lex(source);
+        assert!(errors.is_empty(), "unexpected errors: {:?}", errors);
+
+        tokens.into_iter().map(|token| token.into_kind()).collect()
+    }
+
+    #[test]
+    fn keywords_and_idents() {
+        assert_eq!(
+            kinds("fn main let x_1 true"),
+            vec![
+                Fn,
+                Ident("main".to_string()),
+                Let,
+                Ident("x_1".to_string()),
+                Boolean(true),
+                Eof,
+            ],
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(
+            kinds("12 3.5 1_000 t.0.1"),
+            vec![
+                Integer(12),
+                Float(3.5),
+                Integer(1000),
+                Ident("t".to_string()),
+                Dot,
+                Integer(0),
+                Dot,
+                Integer(1),
+                Eof,
+            ],
+        );
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            kinds("** <= << && || :: != // comment \n="),
+            vec![
+                StarStar,
+                LtEq,
+                LtLt,
+                AndAnd,
+                OrOr,
+                ColonColon,
+                BangEq,
+                Comment("// comment".to_string()),
+                Eq,
+                Eof,
+            ],
+        );
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            kinds(r#""a\n\"b\" \u{e9}""#),
+            vec![String("a\n\"b\" é".to_string()), Eof],
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let (tokens, _) = Lexer::lex("let\n  café = 1;");
+
+        assert_eq!(tokens[0].span(), Span::new(0, 3, 1, 1));
+        assert_eq!(tokens[1].span(), Span::new(6, 11, 2, 3));
+        assert_eq!(tokens[2].span(), Span::new(12, 13, 2, 8));
+    }
+
+    #[test]
+    fn errors() {
+        let (tokens, errors) = Lexer::lex("\"abc");
+        assert_eq!(tokens[0].kind(), &String("abc".to_string()));
+        match errors.as_slice() {
+            [Error::UnterminatedString { span }] => {
+                assert_eq!(*span, Span::new(0, 4, 1, 1));
+            }
+            errors => panic!("unexpected errors: {:?}", errors),
+        }
+
+        let (tokens, errors) = Lexer::lex("a @ b");
+        assert_eq!(tokens.len(), 3);
+        match errors.as_slice() {
+            [Error::UnexpectedChar { ch: '@', span }] => {
+                assert_eq!(*span, Span::new(2, 3, 1, 3));
+            }
+            errors => panic!("unexpected errors: {:?}", errors),
+        }
+    }
+
+    #[test]
+    fn recovers_after_invalid_escape() {
+        let (tokens, errors) = Lexer::lex(r#""a\qb" c"#);
+
+        assert_eq!(tokens[0].kind(), &String("ab".to_string()));
+        assert_eq!(tokens[1].kind(), &Ident("c".to_string()));
+        match errors.as_slice() {
+            [Error::InvalidEscape { escape, span }] => {
+                assert_eq!(escape, "q");
+                assert_eq!(*span, Span::new(2, 4, 1, 3));
+            }
+            errors => panic!("unexpected errors: {:?}", errors),
+        }
+    }
+}
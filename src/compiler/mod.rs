@@ -1 +1,9 @@
+pub mod ast;
+pub mod capture;
+pub mod diagnostic;
+pub mod error;
+pub mod formatter;
 pub mod generator;
+pub mod lexer;
+pub mod parser;
+pub mod span;
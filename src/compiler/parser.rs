@@ -0,0 +1,1182 @@
+use std::convert::TryFrom;
+use std::mem;
+
+use super::ast::*;
+use super::diagnostic::Diagnostic;
+use super::error::{Error, Result};
+use super::lexer::{Lexer, Token, TokenKind};
+use super::span::Span;
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    comments: Vec<Comment>,
+    pos: usize,
+    prev_span: Span,
+    errors: Vec<Error>,
+    // Whether a path followed by `{` starts a struct literal. It does not in
+    // the condition of an `if` or `while`, where the `{` starts the body.
+    struct_literals: bool,
+}
+
+impl Parser {
+    /// Creates a parser over `tokens`, which must end with a
+    /// `TokenKind::Eof` as produced by `Lexer::lex`. Comments are set aside
+    /// and attached to the parsed file.
+    pub fn new(tokens: Vec<Token>) -> Parser {
+        let mut comments = Vec::new();
+        let tokens = tokens
+            .into_iter()
+            .filter_map(|token| {
+                let span = token.span();
+                match token.into_kind() {
+                    TokenKind::Comment(text) => {
+                        comments.push(Comment { text, span });
+                        None
+                    }
+                    kind => Some(Token::new(kind, span)),
+                }
+            })
+            .collect();
+
+        Parser {
+            tokens,
+            comments,
+            pos: 0,
+            prev_span: Span::default(),
+            errors: Vec::new(),
+            struct_literals: true,
+        }
+    }
+
+    /// Parses all of `source`, recovering from errors where possible.
+    ///
+    /// The returned file contains every item and statement that could be
+    /// parsed, and is only complete if there are no diagnostics.
+    pub fn parse(source: &str) -> (File, Vec<Diagnostic>) {
+        let (tokens, lex_errors) = Lexer::lex(source);
+
+        let mut parser = Parser::new(tokens);
+        let file = parser.file();
+
+        let mut errors = lex_errors;
+        errors.extend(parser.errors);
+        errors.sort_by_key(|error| error.span().start());
+
+        (file, errors.into_iter().map(Diagnostic::from).collect())
+    }
+
+    pub fn file(&mut self) -> File {
+        let mut items = Vec::new();
+
+        while !self.check(&TokenKind::Eof) {
+            match self.item() {
+                Ok(item) => items.push(item),
+                Err(error) => {
+                    self.errors.push(error);
+                    self.synchronize_item();
+
+                    // A stray `}` can't close anything at the top level.
+                    if self.check(&TokenKind::CloseBrace) {
+                        self.bump();
+                    }
+                }
+            }
+        }
+
+        File {
+            items,
+            comments: mem::take(&mut self.comments),
+            span: Span::new(0, self.peek().span().end(), 1, 1),
+        }
+    }
+
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    fn item(&mut self) -> Result<Item> {
+        let start = self.peek().span();
+
+        let kind = match self.peek().kind() {
+            TokenKind::Fn => ItemKind::Fn(self.fn_item()?),
+            TokenKind::Struct => ItemKind::Struct(self.struct_item()?),
+            TokenKind::Enum => ItemKind::Enum(self.enum_item()?),
+            TokenKind::Mod => ItemKind::Mod(self.mod_item()?),
+            _ => return Err(self.unexpected("item")),
+        };
+
+        Ok(Item {
+            kind,
+            span: start.to(self.prev_span),
+        })
+    }
+
+    fn fn_item(&mut self) -> Result<FnItem> {
+        self.expect(TokenKind::Fn)?;
+        let ident = self.ident()?;
+
+        self.expect(TokenKind::OpenParen)?;
+        let params = self.comma_list(TokenKind::CloseParen, Parser::ident)?;
+
+        let body = self.block()?;
+
+        Ok(FnItem {
+            ident,
+            params,
+            body,
+        })
+    }
+
+    fn struct_item(&mut self) -> Result<StructItem> {
+        self.expect(TokenKind::Struct)?;
+        let ident = self.ident()?;
+
+        let fields = self.fields()?;
+        if let Fields::Tuple(_) = fields {
+            self.expect(TokenKind::Semi)?;
+        }
+
+        Ok(StructItem { ident, fields })
+    }
+
+    fn enum_item(&mut self) -> Result<EnumItem> {
+        self.expect(TokenKind::Enum)?;
+        let ident = self.ident()?;
+
+        self.expect(TokenKind::OpenBrace)?;
+        let variants = self.comma_list(TokenKind::CloseBrace, |parser| {
+            let ident = parser.ident()?;
+            let fields = parser.fields()?;
+            Ok(Variant {
+                span: ident.span.to(parser.prev_span),
+                ident,
+                fields,
+            })
+        })?;
+
+        Ok(EnumItem { ident, variants })
+    }
+
+    fn fields(&mut self) -> Result<Fields> {
+        if self.eat(&TokenKind::OpenParen) {
+            let fields =
+                self.comma_list(TokenKind::CloseParen, Parser::ident)?;
+            Ok(Fields::Tuple(fields))
+        } else if self.eat(&TokenKind::OpenBrace) {
+            let fields =
+                self.comma_list(TokenKind::CloseBrace, Parser::ident)?;
+            Ok(Fields::Map(fields))
+        } else {
+            Ok(Fields::Tuple(Vec::new()))
+        }
+    }
+
+    fn mod_item(&mut self) -> Result<ModItem> {
+        self.expect(TokenKind::Mod)?;
+        let ident = self.ident()?;
+
+        self.expect(TokenKind::OpenBrace)?;
+        let mut items = Vec::new();
+        while !self.check(&TokenKind::CloseBrace) {
+            if self.check(&TokenKind::Eof) {
+                break;
+            }
+
+            match self.item() {
+                Ok(item) => items.push(item),
+                Err(error) => {
+                    self.errors.push(error);
+                    self.synchronize_item();
+                }
+            }
+        }
+        self.expect(TokenKind::CloseBrace)?;
+
+        Ok(ModItem { ident, items })
+    }
+
+    fn block(&mut self) -> Result<Block> {
+        let start = self.expect(TokenKind::OpenBrace)?.span();
+        let struct_literals = mem::replace(&mut self.struct_literals, true);
+
+        let mut stmts = Vec::new();
+        let mut expr = None;
+
+        while !self.check(&TokenKind::CloseBrace) {
+            // An item keyword can't start a statement, so the block is most
+            // likely missing its `}`. Leave the item to be parsed by the
+            // enclosing item list.
+            if self.is_at_item() || self.check(&TokenKind::Eof) {
+                break;
+            }
+
+            if let Err(error) = self.block_stmt(&mut stmts, &mut expr) {
+                self.errors.push(error);
+                self.synchronize_stmt();
+            }
+        }
+
+        // Keep what was parsed of the block even if its `}` is missing.
+        if let Err(error) = self.expect(TokenKind::CloseBrace) {
+            self.errors.push(error);
+        }
+        self.struct_literals = struct_literals;
+
+        Ok(Block {
+            stmts,
+            expr,
+            span: start.to(self.prev_span),
+        })
+    }
+
+    /// Parses the next statement of a block into `stmts`, or into `expr` if
+    /// it is the value of the block.
+    fn block_stmt(
+        &mut self,
+        stmts: &mut Vec<Stmt>,
+        expr: &mut Option<Box<Expr>>,
+    ) -> Result<()> {
+        let stmt_start = self.peek().span();
+
+        if self.eat(&TokenKind::Semi) {
+            return Ok(());
+        }
+
+        if self.eat(&TokenKind::Let) {
+            let ident = self.ident()?;
+            self.expect(TokenKind::Eq)?;
+            let value = self.expr()?;
+            self.expect(TokenKind::Semi)?;
+
+            stmts.push(Stmt {
+                kind: StmtKind::Let { ident, value },
+                span: stmt_start.to(self.prev_span),
+            });
+            return Ok(());
+        }
+
+        let value = self.expr()?;
+
+        if self.eat(&TokenKind::Semi) {
+            stmts.push(Stmt {
+                kind: StmtKind::Expr(value),
+                span: stmt_start.to(self.prev_span),
+            });
+        } else if self.check(&TokenKind::CloseBrace) {
+            *expr = Some(Box::new(value));
+        } else if value.kind.is_block_like() {
+            stmts.push(Stmt {
+                span: value.span,
+                kind: StmtKind::Expr(value),
+            });
+        } else {
+            return Err(self.unexpected(&TokenKind::Semi.to_string()));
+        }
+
+        Ok(())
+    }
+
+    pub fn expr(&mut self) -> Result<Expr> {
+        let target = self.binary(1)?;
+
+        if self.eat(&TokenKind::Eq) {
+            match target.kind {
+                ExprKind::Path(_)
+                | ExprKind::Field { .. }
+                | ExprKind::Index { .. } => {}
+                // The assignment is still parsed, since the rest of it is
+                // well-formed.
+                _ => self
+                    .errors
+                    .push(Error::InvalidAssignTarget { span: target.span }),
+            }
+
+            let value = self.expr()?;
+            return Ok(Expr {
+                span: target.span.to(value.span),
+                kind: ExprKind::Assign {
+                    target: Box::new(target),
+                    value: Box::new(value),
+                },
+            });
+        }
+
+        Ok(target)
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr> {
+        let mut left = self.unary()?;
+
+        while let Some(op) = self.binary_op() {
+            let precedence = op.precedence();
+            if precedence < min_precedence {
+                break;
+            }
+            self.bump();
+
+            let right = if op.is_right_assoc() {
+                self.binary(precedence)?
+            } else {
+                self.binary(precedence + 1)?
+            };
+
+            left = Expr {
+                span: left.span.to(right.span),
+                kind: ExprKind::Binary {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                },
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn binary_op(&self) -> Option<BinaryOp> {
+        use self::TokenKind::*;
+
+        let op = match self.peek().kind() {
+            OrOr => BinaryOp::Or,
+            AndAnd => BinaryOp::And,
+            EqEq => BinaryOp::Equal,
+            BangEq => BinaryOp::NotEqual,
+            Lt => BinaryOp::Less,
+            LtEq => BinaryOp::LessEqual,
+            Gt => BinaryOp::Greater,
+            GtEq => BinaryOp::GreaterEqual,
+            Or => BinaryOp::BitOr,
+            Caret => BinaryOp::BitXor,
+            And => BinaryOp::BitAnd,
+            LtLt => BinaryOp::ShiftLeft,
+            GtGt => BinaryOp::ShiftRight,
+            Plus => BinaryOp::Add,
+            Minus => BinaryOp::Subtract,
+            Star => BinaryOp::Multiply,
+            Slash => BinaryOp::Divide,
+            Percent => BinaryOp::Remainder,
+            StarStar => BinaryOp::Power,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        let op = match self.peek().kind() {
+            TokenKind::Minus => UnaryOp::Negate,
+            TokenKind::Bang => UnaryOp::Not,
+            _ => return self.postfix(),
+        };
+        let start = self.bump().span();
+
+        // Unary operators bind looser than `**`, so `-a ** b` is
+        // `-(a ** b)`.
+        let expr = self.binary(BinaryOp::Power.precedence())?;
+
+        Ok(Expr {
+            span: start.to(expr.span),
+            kind: ExprKind::Unary {
+                op,
+                expr: Box::new(expr),
+            },
+        })
+    }
+
+    fn postfix(&mut self) -> Result<Expr> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.eat(&TokenKind::OpenParen) {
+                let args =
+                    self.comma_list(TokenKind::CloseParen, Parser::expr)?;
+                expr = Expr {
+                    span: expr.span.to(self.prev_span),
+                    kind: ExprKind::Call {
+                        callee: Box::new(expr),
+                        args,
+                    },
+                };
+            } else if self.eat(&TokenKind::Dot) {
+                expr = self.field_or_method_call(expr)?;
+            } else if self.eat(&TokenKind::OpenBracket) {
+                let index = self.with_struct_literals(true, Parser::expr)?;
+                self.expect(TokenKind::CloseBracket)?;
+                expr = Expr {
+                    span: expr.span.to(self.prev_span),
+                    kind: ExprKind::Index {
+                        expr: Box::new(expr),
+                        index: Box::new(index),
+                    },
+                };
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn field_or_method_call(&mut self, expr: Expr) -> Result<Expr> {
+        if let TokenKind::Integer(field) = *self.peek().kind() {
+            let span = self.bump().span();
+            let field =
+                u8::try_from(field).map_err(|_| Error::InvalidNumber {
+                    number: field.to_string(),
+                    span,
+                })?;
+
+            return Ok(Expr {
+                span: expr.span.to(span),
+                kind: ExprKind::Field {
+                    expr: Box::new(expr),
+                    field: Field::Positional(field, span),
+                },
+            });
+        }
+
+        let ident = self.ident()?;
+
+        if self.eat(&TokenKind::OpenParen) {
+            let args = self.comma_list(TokenKind::CloseParen, Parser::expr)?;
+            Ok(Expr {
+                span: expr.span.to(self.prev_span),
+                kind: ExprKind::MethodCall {
+                    receiver: Box::new(expr),
+                    ident,
+                    args,
+                },
+            })
+        } else {
+            Ok(Expr {
+                span: expr.span.to(ident.span),
+                kind: ExprKind::Field {
+                    expr: Box::new(expr),
+                    field: Field::Named(ident),
+                },
+            })
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let start = self.peek().span();
+
+        let kind = match self.peek().kind().clone() {
+            TokenKind::Boolean(boolean) => {
+                self.bump();
+                ExprKind::Literal(Literal::Boolean(boolean))
+            }
+            TokenKind::Integer(integer) => {
+                self.bump();
+                ExprKind::Literal(Literal::Integer(integer))
+            }
+            TokenKind::Float(float) => {
+                self.bump();
+                ExprKind::Literal(Literal::Float(float))
+            }
+            TokenKind::String(string) => {
+                self.bump();
+                ExprKind::Literal(Literal::String(string))
+            }
+            TokenKind::Ident(_) => {
+                let path = self.path()?;
+                if self.struct_literals && self.eat(&TokenKind::OpenBrace) {
+                    let fields = self.comma_list(
+                        TokenKind::CloseBrace,
+                        Parser::struct_field,
+                    )?;
+                    ExprKind::Struct { path, fields }
+                } else {
+                    ExprKind::Path(path)
+                }
+            }
+            TokenKind::OpenParen => return self.paren_or_tuple(),
+            TokenKind::OpenBracket => self.list_or_map()?,
+            TokenKind::OpenBrace => ExprKind::Block(self.block()?),
+            TokenKind::If => return self.if_expr(),
+            TokenKind::While => {
+                self.bump();
+                let cond = self.with_struct_literals(false, Parser::expr)?;
+                let body = self.block()?;
+                ExprKind::While {
+                    cond: Box::new(cond),
+                    body,
+                }
+            }
+            TokenKind::Fn => {
+                self.bump();
+                self.expect(TokenKind::OpenParen)?;
+                let params =
+                    self.comma_list(TokenKind::CloseParen, Parser::ident)?;
+                let body = self.block()?;
+                ExprKind::Closure { params, body }
+            }
+            TokenKind::Return => {
+                self.bump();
+                if self.can_begin_expr() {
+                    ExprKind::Return(Some(Box::new(self.expr()?)))
+                } else {
+                    ExprKind::Return(None)
+                }
+            }
+            _ => return Err(self.unexpected("expression")),
+        };
+
+        Ok(Expr {
+            kind,
+            span: start.to(self.prev_span),
+        })
+    }
+
+    fn paren_or_tuple(&mut self) -> Result<Expr> {
+        let start = self.expect(TokenKind::OpenParen)?.span();
+
+        let mut exprs = Vec::new();
+        let mut is_tuple = true;
+
+        while !self.eat(&TokenKind::CloseParen) {
+            exprs.push(self.with_struct_literals(true, Parser::expr)?);
+
+            if self.eat(&TokenKind::CloseParen) {
+                is_tuple = exprs.len() != 1;
+                break;
+            }
+            self.expect(TokenKind::Comma)?;
+        }
+
+        if is_tuple {
+            Ok(Expr {
+                kind: ExprKind::Tuple(exprs),
+                span: start.to(self.prev_span),
+            })
+        } else {
+            Ok(exprs.pop().unwrap())
+        }
+    }
+
+    /// Parses either a list, or a map if the first element is followed by a
+    /// `:`. An empty map is written `[:]`.
+    fn list_or_map(&mut self) -> Result<ExprKind> {
+        self.expect(TokenKind::OpenBracket)?;
+
+        if self.eat(&TokenKind::Colon) {
+            self.expect(TokenKind::CloseBracket)?;
+            return Ok(ExprKind::Map(Vec::new()));
+        }
+        if self.eat(&TokenKind::CloseBracket) {
+            return Ok(ExprKind::List(Vec::new()));
+        }
+
+        let first = self.with_struct_literals(true, Parser::expr)?;
+        if !self.eat(&TokenKind::Colon) {
+            let mut exprs = vec![first];
+            if self.eat(&TokenKind::Comma) {
+                exprs.extend(
+                    self.comma_list(TokenKind::CloseBracket, Parser::expr)?,
+                );
+            } else {
+                self.expect(TokenKind::CloseBracket)?;
+            }
+            return Ok(ExprKind::List(exprs));
+        }
+
+        let value = self.with_struct_literals(true, Parser::expr)?;
+        let mut entries = vec![(first, value)];
+        if self.eat(&TokenKind::Comma) {
+            entries.extend(
+                self.comma_list(TokenKind::CloseBracket, Parser::map_entry)?,
+            );
+        } else {
+            self.expect(TokenKind::CloseBracket)?;
+        }
+        Ok(ExprKind::Map(entries))
+    }
+
+    fn map_entry(&mut self) -> Result<(Expr, Expr)> {
+        let key = self.expr()?;
+        self.expect(TokenKind::Colon)?;
+        let value = self.expr()?;
+        Ok((key, value))
+    }
+
+    fn struct_field(&mut self) -> Result<(Ident, Expr)> {
+        let ident = self.ident()?;
+        self.expect(TokenKind::Colon)?;
+        let value = self.expr()?;
+        Ok((ident, value))
+    }
+
+    fn if_expr(&mut self) -> Result<Expr> {
+        let start = self.expect(TokenKind::If)?.span();
+
+        let cond = self.with_struct_literals(false, Parser::expr)?;
+        let then_block = self.block()?;
+
+        let else_expr = if self.eat(&TokenKind::Else) {
+            if self.check(&TokenKind::If) {
+                Some(Box::new(self.if_expr()?))
+            } else {
+                let block = self.block()?;
+                Some(Box::new(Expr {
+                    span: block.span,
+                    kind: ExprKind::Block(block),
+                }))
+            }
+        } else {
+            None
+        };
+
+        Ok(Expr {
+            kind: ExprKind::If {
+                cond: Box::new(cond),
+                then_block,
+                else_expr,
+            },
+            span: start.to(self.prev_span),
+        })
+    }
+
+    fn path(&mut self) -> Result<Path> {
+        let mut segments = vec![self.ident()?];
+        while self.eat(&TokenKind::ColonColon) {
+            segments.push(self.ident()?);
+        }
+
+        Ok(Path {
+            span: segments[0].span.to(self.prev_span),
+            segments,
+        })
+    }
+
+    fn ident(&mut self) -> Result<Ident> {
+        if let TokenKind::Ident(name) = self.peek().kind() {
+            let name = name.clone();
+            let span = self.bump().span();
+            Ok(Ident { name, span })
+        } else {
+            Err(self.unexpected("identifier"))
+        }
+    }
+
+    /// Parses elements separated by commas, allowing a trailing comma, up to
+    /// and including the `close` token.
+    fn comma_list<T, F>(&mut self, close: TokenKind, mut f: F) -> Result<Vec<T>>
+    where
+        F: FnMut(&mut Parser) -> Result<T>,
+    {
+        let mut elements = Vec::new();
+
+        while !self.eat(&close) {
+            elements.push(self.with_struct_literals(true, &mut f)?);
+
+            if !self.eat(&TokenKind::Comma) {
+                self.expect(close)?;
+                break;
+            }
+        }
+
+        Ok(elements)
+    }
+
+    /// Calls `f` with struct literals allowed or not, as in `allowed`.
+    fn with_struct_literals<T, F>(&mut self, allowed: bool, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Parser) -> Result<T>,
+    {
+        let struct_literals = mem::replace(&mut self.struct_literals, allowed);
+        let result = f(self);
+        self.struct_literals = struct_literals;
+        result
+    }
+
+    /// Skips to the end of the current statement, which is either just after
+    /// a `;` or just before the `}` closing the enclosing block. Nested
+    /// delimiters are skipped over.
+    fn synchronize_stmt(&mut self) {
+        let mut depth = 0usize;
+
+        loop {
+            match self.peek().kind() {
+                TokenKind::Eof => return,
+                TokenKind::Semi if depth == 0 => {
+                    self.bump();
+                    return;
+                }
+                TokenKind::CloseBrace if depth == 0 => return,
+                TokenKind::Let if depth == 0 => return,
+                _ if depth == 0 && self.is_at_item() => return,
+                TokenKind::OpenParen
+                | TokenKind::OpenBrace
+                | TokenKind::OpenBracket => depth += 1,
+                TokenKind::CloseParen
+                | TokenKind::CloseBrace
+                | TokenKind::CloseBracket => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.bump();
+        }
+    }
+
+    /// Skips to the start of the next item, or to the `}` closing the
+    /// enclosing module. Nested delimiters are skipped over.
+    fn synchronize_item(&mut self) {
+        let mut depth = 0usize;
+
+        loop {
+            match self.peek().kind() {
+                TokenKind::Eof => return,
+                TokenKind::CloseBrace if depth == 0 => return,
+                _ if depth == 0 && self.is_at_item() => return,
+                TokenKind::OpenParen
+                | TokenKind::OpenBrace
+                | TokenKind::OpenBracket => depth += 1,
+                TokenKind::CloseParen
+                | TokenKind::CloseBrace
+                | TokenKind::CloseBracket => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.bump();
+        }
+    }
+
+    fn is_at_item(&self) -> bool {
+        match self.peek().kind() {
+            // `fn(` starts a closure rather than a function item.
+            TokenKind::Fn => self.peek_next().kind() != &TokenKind::OpenParen,
+            TokenKind::Struct | TokenKind::Enum | TokenKind::Mod => true,
+            _ => false,
+        }
+    }
+
+    fn can_begin_expr(&self) -> bool {
+        use self::TokenKind::*;
+
+        !matches!(
+            self.peek().kind(),
+            Semi | Comma | CloseParen | CloseBrace | CloseBracket | Eof
+        )
+    }
+
+    fn unexpected(&self, expected: &str) -> Error {
+        Error::UnexpectedToken {
+            expected: expected.to_string(),
+            found: self.peek().kind().clone(),
+            span: self.peek().span(),
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<Token> {
+        if self.check(&kind) {
+            Ok(self.bump())
+        } else {
+            Err(self.unexpected(&kind.to_string()))
+        }
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.check(kind) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn check(&self, kind: &TokenKind) -> bool {
+        mem::discriminant(self.peek().kind()) == mem::discriminant(kind)
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn peek_next(&self) -> &Token {
+        let last = self.tokens.len() - 1;
+        &self.tokens[(self.pos + 1).min(last)]
+    }
+
+    fn bump(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind() != &TokenKind::Eof {
+            self.pos += 1;
+        }
+        self.prev_span = token.span();
+        token
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::ast::*;
+    use crate::compiler::error::Error;
+    use crate::compiler::lexer::Lexer;
+    use crate::compiler::span::Span;
+
+    use super::Parser;
+
+    fn parse(source: &str) -> File {
+        let (file, diagnostics) = Parser::parse(source);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        file
+    }
+
+    fn parse_with_errors(source: &str) -> (File, Vec<Error>) {
+        let (tokens, errors) = Lexer::lex(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let mut parser = Parser::new(tokens);
+        let file = parser.file();
+        (file, parser.errors)
+    }
+
+    fn parse_expr(source: &str) -> Expr {
+        let source = format!("fn main() {{ {} }}", source);
+        let file = parse(&source);
+
+        match file.items.into_iter().next().unwrap().kind {
+            ItemKind::Fn(fn_item) => *fn_item.body.expr.unwrap(),
+            kind => panic!("unexpected item: {:?}", kind),
+        }
+    }
+
+    fn binary(expr: &Expr) -> (BinaryOp, &Expr, &Expr) {
+        match &expr.kind {
+            ExprKind::Binary { op, left, right } => (*op, left, right),
+            kind => panic!("unexpected expression: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn items() {
+        let file = parse(
+            "fn add(a, b) { a + b }
+             struct Point { x, y }
+             struct Pair(left, right);
+             enum Option { Some(value), None }
+             mod math { fn zero() { 0 } }",
+        );
+
+        assert_eq!(file.items.len(), 5);
+
+        match &file.items[0].kind {
+            ItemKind::Fn(fn_item) => {
+                assert_eq!(fn_item.ident.name, "add");
+                assert_eq!(fn_item.params.len(), 2);
+            }
+            kind => panic!("unexpected item: {:?}", kind),
+        }
+        match &file.items[2].kind {
+            ItemKind::Struct(struct_item) => match &struct_item.fields {
+                Fields::Tuple(fields) => assert_eq!(fields.len(), 2),
+                fields => panic!("unexpected fields: {:?}", fields),
+            },
+            kind => panic!("unexpected item: {:?}", kind),
+        }
+        match &file.items[3].kind {
+            ItemKind::Enum(enum_item) => {
+                assert_eq!(enum_item.variants.len(), 2);
+                assert_eq!(
+                    enum_item.variants[1].fields,
+                    Fields::Tuple(Vec::new()),
+                );
+            }
+            kind => panic!("unexpected item: {:?}", kind),
+        }
+        assert_eq!(file.items[4].span, Span::new(157, 185, 5, 14));
+    }
+
+    #[test]
+    fn closures() {
+        let file = parse(
+            "fn main() { let f = fn(x, y) { x + y }; fn() {}() }
+             fn other() {}",
+        );
+        assert_eq!(file.items.len(), 2);
+
+        let body = match &file.items[0].kind {
+            ItemKind::Fn(fn_item) => &fn_item.body,
+            kind => panic!("unexpected item: {:?}", kind),
+        };
+        match &body.stmts[0].kind {
+            StmtKind::Let { value, .. } => match &value.kind {
+                ExprKind::Closure { params, body } => {
+                    assert_eq!(params.len(), 2);
+                    assert!(body.expr.is_some());
+                }
+                kind => panic!("unexpected expression: {:?}", kind),
+            },
+            kind => panic!("unexpected statement: {:?}", kind),
+        }
+        match &body.expr.as_ref().unwrap().kind {
+            ExprKind::Call { callee, args } => {
+                assert!(args.is_empty());
+                match &callee.kind {
+                    ExprKind::Closure { params, .. } => {
+                        assert!(params.is_empty())
+                    }
+                    kind => panic!("unexpected expression: {:?}", kind),
+                }
+            }
+            kind => panic!("unexpected expression: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        let expr = parse_expr("1 + 2 * 3 == 7 || false");
+        let (op, left, _) = binary(&expr);
+        assert_eq!(op, BinaryOp::Or);
+
+        let (op, left, _) = binary(left);
+        assert_eq!(op, BinaryOp::Equal);
+
+        let (op, _, right) = binary(left);
+        assert_eq!(op, BinaryOp::Add);
+        assert_eq!(binary(right).0, BinaryOp::Multiply);
+    }
+
+    #[test]
+    fn power_is_right_assoc() {
+        let expr = parse_expr("-2 ** 3 ** 2");
+
+        match expr.kind {
+            ExprKind::Unary {
+                op: UnaryOp::Negate,
+                expr,
+            } => {
+                let (op, _, right) = binary(&expr);
+                assert_eq!(op, BinaryOp::Power);
+                assert_eq!(binary(right).0, BinaryOp::Power);
+            }
+            kind => panic!("unexpected expression: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn postfix() {
+        let expr = parse_expr("math::max(a, b).0.len()");
+
+        match expr.kind {
+            ExprKind::MethodCall {
+                receiver, ident, ..
+            } => {
+                assert_eq!(ident.name, "len");
+                match receiver.kind {
+                    ExprKind::Field {
+                        field: Field::Positional(0, _),
+                        ..
+                    } => {}
+                    kind => panic!("unexpected expression: {:?}", kind),
+                }
+            }
+            kind => panic!("unexpected expression: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn lists() {
+        match parse_expr("[1, [2], []][0]").kind {
+            ExprKind::Index { expr, index } => {
+                match expr.kind {
+                    ExprKind::List(exprs) => assert_eq!(exprs.len(), 3),
+                    kind => panic!("unexpected expression: {:?}", kind),
+                }
+                assert_eq!(index.kind, ExprKind::Literal(Literal::Integer(0)));
+            }
+            kind => panic!("unexpected expression: {:?}", kind),
+        }
+
+        let file = parse("fn main() { xs[i] = xs[i + 1]; }");
+        match &file.items[0].kind {
+            ItemKind::Fn(fn_item) => match &fn_item.body.stmts[0].kind {
+                StmtKind::Expr(Expr {
+                    kind: ExprKind::Assign { target, .. },
+                    ..
+                }) => match target.kind {
+                    ExprKind::Index { .. } => {}
+                    ref kind => panic!("unexpected expression: {:?}", kind),
+                },
+                kind => panic!("unexpected statement: {:?}", kind),
+            },
+            kind => panic!("unexpected item: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn maps() {
+        match parse_expr("[\"a\": 1, \"b\": [:],]").kind {
+            ExprKind::Map(entries) => {
+                assert_eq!(entries.len(), 2);
+                assert_eq!(entries[1].1.kind, ExprKind::Map(Vec::new()));
+            }
+            kind => panic!("unexpected expression: {:?}", kind),
+        }
+
+        let (_, errors) = parse_with_errors("fn main() { [1: 2, 3] }");
+        match errors.as_slice() {
+            [Error::UnexpectedToken { expected, .. }] => {
+                assert_eq!(expected, "`:`")
+            }
+            errors => panic!("unexpected errors: {:?}", errors),
+        }
+    }
+
+    #[test]
+    fn statements() {
+        let file = parse(
+            "fn main() {
+                 let x = 1;
+                 while x < 10 { x = x + 1; }
+                 if x == 10 { x } else { 0 }
+             }",
+        );
+
+        match &file.items[0].kind {
+            ItemKind::Fn(fn_item) => {
+                assert_eq!(fn_item.body.stmts.len(), 2);
+                match &fn_item.body.expr.as_ref().unwrap().kind {
+                    ExprKind::If { else_expr, .. } => {
+                        assert!(else_expr.is_some())
+                    }
+                    kind => panic!("unexpected expression: {:?}", kind),
+                }
+            }
+            kind => panic!("unexpected item: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn tuples() {
+        match parse_expr("(1)").kind {
+            ExprKind::Literal(Literal::Integer(1)) => {}
+            kind => panic!("unexpected expression: {:?}", kind),
+        }
+        match parse_expr("(1,)").kind {
+            ExprKind::Tuple(exprs) => assert_eq!(exprs.len(), 1),
+            kind => panic!("unexpected expression: {:?}", kind),
+        }
+        match parse_expr("()").kind {
+            ExprKind::Tuple(exprs) => assert!(exprs.is_empty()),
+            kind => panic!("unexpected expression: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn struct_literals() {
+        match parse_expr("geo::Point { x: 1, y: (Size {},) }").kind {
+            ExprKind::Struct { path, fields } => {
+                assert_eq!(path.segments.len(), 2);
+                assert_eq!(fields.len(), 2);
+                assert_eq!(fields[1].0.name, "y");
+            }
+            kind => panic!("unexpected expression: {:?}", kind),
+        }
+
+        // In a condition, the `{` after a path starts the body.
+        match parse_expr("if p == (P { x: 1 }) { P { x: 2 } }").kind {
+            ExprKind::If {
+                cond, then_block, ..
+            } => {
+                assert!(matches!(cond.kind, ExprKind::Binary { .. }));
+                assert!(matches!(
+                    then_block.expr.unwrap().kind,
+                    ExprKind::Struct { .. }
+                ));
+            }
+            kind => panic!("unexpected expression: {:?}", kind),
+        }
+
+        let (_, errors) =
+            parse_with_errors("fn main() { while P { x: 1 } {} }");
+        assert!(!errors.is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let (_, errors) = parse_with_errors("fn main() { let = 1; }");
+        match errors.as_slice() {
+            [Error::UnexpectedToken { expected, span, .. }] => {
+                assert_eq!(expected, "identifier");
+                assert_eq!(*span, Span::new(16, 17, 1, 17));
+            }
+            errors => panic!("unexpected errors: {:?}", errors),
+        }
+
+        let (_, errors) = parse_with_errors("fn main() { 1 = 2; }");
+        match errors.as_slice() {
+            [Error::InvalidAssignTarget { .. }] => {}
+            errors => panic!("unexpected errors: {:?}", errors),
+        }
+
+        let (_, errors) = parse_with_errors("fn main() { a b }");
+        match errors.as_slice() {
+            [Error::UnexpectedToken { expected, .. }] => {
+                assert_eq!(expected, "`;`");
+            }
+            errors => panic!("unexpected errors: {:?}", errors),
+        }
+    }
+
+    #[test]
+    fn recovers_within_blocks() {
+        let (file, errors) = parse_with_errors(
+            "fn main() {
+                 let x = ;
+                 let y = (1 +);
+                 if x { y = ) } else { 2 }
+                 x + y
+             }",
+        );
+
+        assert_eq!(errors.len(), 3);
+        match &file.items[0].kind {
+            ItemKind::Fn(fn_item) => {
+                assert_eq!(fn_item.body.stmts.len(), 1);
+                assert!(fn_item.body.expr.is_some());
+            }
+            kind => panic!("unexpected item: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn recovers_between_items() {
+        let (file, errors) = parse_with_errors(
+            "fn broken( { 1 }
+             let stray = 1;
+             }
+             struct Point { x, y }
+             mod m { fn f() { 1 } struct ; fn g() { 2 } }
+             fn unclosed() { 1
+             fn last() { 2 }",
+        );
+
+        let idents: Vec<_> = file
+            .items
+            .iter()
+            .map(|item| match &item.kind {
+                ItemKind::Fn(fn_item) => fn_item.ident.name.as_str(),
+                ItemKind::Struct(struct_item) => {
+                    struct_item.ident.name.as_str()
+                }
+                ItemKind::Enum(enum_item) => enum_item.ident.name.as_str(),
+                ItemKind::Mod(mod_item) => {
+                    assert_eq!(mod_item.items.len(), 2);
+                    mod_item.ident.name.as_str()
+                }
+            })
+            .collect();
+
+        assert_eq!(idents, vec!["Point", "m", "unclosed", "last"]);
+        assert_eq!(errors.len(), 4);
+    }
+
+    #[test]
+    fn diagnostics_are_ordered() {
+        let (file, diagnostics) =
+            Parser::parse("fn main() { let s = \"\\q\"; let = 1; @ }");
+
+        assert_eq!(file.items.len(), 1);
+        let codes: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.code().unwrap())
+            .collect();
+        assert_eq!(codes, vec!["E0003", "E0005", "E0001"]);
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Span {
+    start: usize,
+    end: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    /// Returns a span covering both `self` and `other`, keeping the line and
+    /// column of whichever starts first.
+    pub fn to(&self, other: Span) -> Span {
+        let (first, last) = if self.start <= other.start {
+            (*self, other)
+        } else {
+            (other, *self)
+        };
+
+        Span {
+            start: first.start,
+            end: first.end.max(last.end),
+            line: first.line,
+            column: first.column,
+        }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
@@ -1,5 +1,6 @@
 use super::ident::Ident;
 use super::typ::Kind;
+use super::value::ValueKind;
 
 pub type Result<'gc, T> = std::result::Result<T, Error<'gc>>;
 
@@ -17,4 +18,16 @@ pub enum Error<'gc> {
         received
     )]
     InvalidKind { expected: Kind, received: Kind },
+
+    #[error(display = "invalid variant: {}", variant)]
+    InvalidVariant { variant: u8 },
+
+    #[error(display = "unhashable key: {}", kind)]
+    UnhashableKey { kind: ValueKind },
+
+    #[error(display = "value nested more than {} deep", max_depth)]
+    NestingTooDeep { max_depth: usize },
+
+    #[error(display = "unordered values: {} and {}", left, right)]
+    UnorderedValues { left: ValueKind, right: ValueKind },
 }
@@ -1,4 +1,6 @@
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ptr;
 
 use eko_gc::{Arena, Gc, Trace};
 
@@ -11,17 +13,18 @@ use super::value::Value;
 pub struct Fn<'gc>(Gc<'gc, FnData<'gc>>);
 
 impl<'gc> Fn<'gc> {
-    // TODO: Add in all the required parameters.
     pub fn new_chunk(
         arena: &Arena<'gc>,
+        modu: Mod<'gc>,
+        ident: Ident<'gc>,
         arity: u8,
         chunk: Chunk<'gc>,
     ) -> Fn<'gc> {
         Fn(Gc::new(
             arena,
             FnData {
-                modu: Mod::new(arena),
-                ident: Ident::new_number(0),
+                modu,
+                ident,
                 arity,
                 is_method: false,
                 proto: FnProto::Chunk(chunk),
@@ -29,17 +32,18 @@ impl<'gc> Fn<'gc> {
         ))
     }
 
-    // TODO: Add in all the required parameters.
     pub fn new_external(
         arena: &Arena<'gc>,
+        modu: Mod<'gc>,
+        ident: Ident<'gc>,
         arity: u8,
         external: External<'gc>,
     ) -> Fn<'gc> {
         Fn(Gc::new(
             arena,
             FnData {
-                modu: Mod::new(arena),
-                ident: Ident::new_number(0),
+                modu,
+                ident,
                 arity,
                 is_method: false,
                 proto: FnProto::External(external),
@@ -47,6 +51,10 @@ impl<'gc> Fn<'gc> {
         ))
     }
 
+    pub fn modu(&self) -> &Mod<'gc> {
+        &self.0.modu
+    }
+
     pub fn ident(&self) -> &Ident<'gc> {
         &self.0.ident
     }
@@ -64,6 +72,18 @@ impl<'gc> Fn<'gc> {
     }
 }
 
+impl<'gc> PartialEq for Fn<'gc> {
+    fn eq(&self, other: &Fn<'gc>) -> bool {
+        Gc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<'gc> Hash for Fn<'gc> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        ptr::hash(&*self.0, state);
+    }
+}
+
 #[derive(Debug, Trace)]
 pub struct FnData<'gc> {
     modu: Mod<'gc>,
@@ -95,16 +115,23 @@ impl<'gc> fmt::Debug for FnProto<'gc> {
 pub struct Chunk<'gc>(Gc<'gc, ChunkData<'gc>>);
 
 impl<'gc> Chunk<'gc> {
+    /// Creates a chunk where `lines` holds the source line of each
+    /// instruction, if known. Variables captured by closures are kept apart
+    /// from the local ones, in a scope of `captured_scope_len` variables.
     pub fn new(
         arena: &Arena<'gc>,
         local_scope_len: usize,
+        captured_scope_len: usize,
         instrs: Vec<Instr<'gc>>,
+        lines: Vec<Option<usize>>,
     ) -> Chunk<'gc> {
         Chunk(Gc::new(
             arena,
             ChunkData {
                 local_scope_len,
+                captured_scope_len,
                 instrs,
+                lines,
             },
         ))
     }
@@ -113,15 +140,33 @@ impl<'gc> Chunk<'gc> {
         self.0.local_scope_len
     }
 
+    pub fn captured_scope_len(&self) -> usize {
+        self.0.captured_scope_len
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.instrs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.instrs.is_empty()
+    }
+
     pub fn instr(&self, index: usize) -> Option<Instr<'gc>> {
         self.0.instrs.get(index).cloned()
     }
+
+    pub fn line(&self, index: usize) -> Option<usize> {
+        self.0.lines.get(index).cloned().flatten()
+    }
 }
 
 #[derive(Debug, Trace)]
 pub struct ChunkData<'gc> {
     local_scope_len: usize,
+    captured_scope_len: usize,
     instrs: Vec<Instr<'gc>>,
+    lines: Vec<Option<usize>>,
 }
 
 #[derive(Clone, Trace)]
@@ -1,21 +1,129 @@
 use super::fun::Fn;
+use super::ident::Ident;
 use super::modu::Mod;
+use super::typ;
 use super::value::Value;
 
 #[derive(Debug, Clone)]
 pub enum Instr<'gc> {
-    PushValue { value: Value<'gc> },
-    PushMod { modu: Mod<'gc> },
-    PushFn { fun: Fn<'gc> },
+    PushValue {
+        value: Value<'gc>,
+    },
+    PushMod {
+        modu: Mod<'gc>,
+    },
+    PushFn {
+        fun: Fn<'gc>,
+    },
+    GetFn {
+        ident: Ident<'gc>,
+    },
     Pop,
 
-    PushVar { var: usize },
-    PopVar { var: usize },
+    PushVar {
+        var: usize,
+    },
+    PopVar {
+        var: usize,
+    },
+    PushCapturedVar {
+        parents: usize,
+        var: usize,
+    },
+    PopCapturedVar {
+        parents: usize,
+        var: usize,
+    },
+    PushCapturedScope {
+        len: usize,
+    },
+    PopCapturedScope,
+    MakeClosure,
+
+    MakeTuple {
+        len: u8,
+    },
+    MakeStruct {
+        typ: typ::Struct<'gc>,
+        fields: Fields<'gc>,
+    },
+    MakeEnum {
+        typ: typ::Enum<'gc>,
+        variant: u8,
+        fields: Fields<'gc>,
+    },
+    MakeList {
+        len: usize,
+    },
+    Index,
+    SetIndex,
+    Len,
+    ListPush,
+    ListPop,
+    Slice,
+    MakeMap {
+        len: usize,
+    },
+    MapInsert,
+    MapRemove,
+    MapContains,
+    MapKeys,
+    CallMethod {
+        ident: Ident<'gc>,
+        arity: u8,
+    },
+
+    GetField {
+        field: Ident<'gc>,
+    },
+    SetField {
+        field: Ident<'gc>,
+    },
 
     Add,
     Subtract,
     Multiply,
     Divide,
+    FloorDivide,
+    Remainder,
+    Power,
+
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Not,
+    Negate,
 
-    Call { arity: u8, is_method: bool },
+    Jump {
+        index: usize,
+    },
+    JumpIfTrue {
+        index: usize,
+    },
+    JumpIfFalse {
+        index: usize,
+    },
+
+    Call {
+        arity: u8,
+        is_method: bool,
+    },
+    Return,
+}
+
+/// The fields popped by `MakeStruct` and `MakeEnum`, in the order they were
+/// pushed.
+#[derive(Debug, Clone)]
+pub enum Fields<'gc> {
+    Tuple(u8),
+    Map(Vec<Ident<'gc>>),
 }
@@ -1,7 +1,8 @@
 use std::collections::BTreeMap;
 
-use eko_gc::{Arena, Gc, RefCell};
+use eko_gc::{Arena, Gc, Ref, RefCell};
 
+use super::fun::Fn;
 use super::ident::Ident;
 use super::typ::Type;
 
@@ -9,26 +10,70 @@ use super::typ::Type;
 pub struct Mod<'gc>(Gc<'gc, RefCell<'gc, ModData<'gc>>>);
 
 impl<'gc> Mod<'gc> {
-    // TODO: Add all the required parameters.
-    pub fn new(arena: &Arena<'gc>) -> Mod<'gc> {
-        Mod(Gc::new(
-            arena,
-            RefCell::new(arena, ModData::new(Ident::new_number(0))),
-        ))
+    pub fn new(arena: &Arena<'gc>, ident: Ident<'gc>) -> Mod<'gc> {
+        Mod(Gc::new(arena, RefCell::new(arena, ModData::new(ident))))
     }
 
-    // TODO: Add all the required parameters.
+    /// Creates a module nested within `parent_mod`, registering it as one of
+    /// the parent's child modules.
     pub fn with_parent_mod(
         arena: &Arena<'gc>,
+        ident: Ident<'gc>,
         parent_mod: Mod<'gc>,
     ) -> Mod<'gc> {
-        Mod(Gc::new(
+        let modu = Mod(Gc::new(
             arena,
             RefCell::new(
                 arena,
-                ModData::with_parent_mod(Ident::new_number(0), parent_mod),
+                ModData::with_parent_mod(ident.clone(), parent_mod.clone()),
             ),
-        ))
+        ));
+        parent_mod
+            .0
+            .borrow_mut()
+            .child_mods
+            .insert(ident, modu.clone());
+        modu
+    }
+
+    pub fn ident(&self) -> Ref<'_, Ident<'gc>> {
+        Ref::map(self.0.borrow(), |data| &data.ident)
+    }
+
+    pub fn parent_mod(&self) -> Option<Mod<'gc>> {
+        self.0.borrow().parent_mod.clone()
+    }
+
+    /// Idents of the module and its parents, from the outermost inwards.
+    pub fn path(&self) -> Vec<Ident<'gc>> {
+        let mut path = vec![self.ident().clone()];
+        let mut next_mod = self.parent_mod();
+        while let Some(modu) = next_mod {
+            path.push(modu.ident().clone());
+            next_mod = modu.parent_mod();
+        }
+        path.reverse();
+        path
+    }
+
+    pub fn child_mod(&self, ident: &Ident<'gc>) -> Option<Mod<'gc>> {
+        self.0.borrow().child_mods.get(ident).cloned()
+    }
+
+    pub fn typ(&self, ident: &Ident<'gc>) -> Option<Type<'gc>> {
+        self.0.borrow().types.get(ident).cloned()
+    }
+
+    pub fn set_type(&self, ident: Ident<'gc>, typ: Type<'gc>) {
+        self.0.borrow_mut().types.insert(ident, typ);
+    }
+
+    pub fn fun(&self, ident: &Ident<'gc>) -> Option<Fn<'gc>> {
+        self.0.borrow().fns.get(ident).cloned()
+    }
+
+    pub fn set_fn(&self, ident: Ident<'gc>, fun: Fn<'gc>) {
+        self.0.borrow_mut().fns.insert(ident, fun);
     }
 }
 
@@ -38,6 +83,7 @@ pub struct ModData<'gc> {
     parent_mod: Option<Mod<'gc>>,
     child_mods: BTreeMap<Ident<'gc>, Mod<'gc>>,
     types: BTreeMap<Ident<'gc>, Type<'gc>>,
+    fns: BTreeMap<Ident<'gc>, Fn<'gc>>,
 }
 
 impl<'gc> ModData<'gc> {
@@ -47,6 +93,7 @@ impl<'gc> ModData<'gc> {
             parent_mod: None,
             child_mods: BTreeMap::new(),
             types: BTreeMap::new(),
+            fns: BTreeMap::new(),
         }
     }
 
@@ -59,6 +106,7 @@ impl<'gc> ModData<'gc> {
             parent_mod: Some(parent_mod),
             child_mods: BTreeMap::new(),
             types: BTreeMap::new(),
+            fns: BTreeMap::new(),
         }
     }
 }
@@ -1,12 +1,13 @@
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::fmt;
 
-use eko_gc::{Gc, Ref, RefCell};
+use eko_gc::{Arena, Gc, Ref, RefCell};
 
 use super::fun::Fn;
 use super::ident::Ident;
 
-#[derive(Debug, Trace)]
+#[derive(Clone, Debug, Trace)]
 pub enum Type<'gc> {
     Struct(Struct<'gc>),
     Enum(Enum<'gc>),
@@ -16,11 +17,31 @@ pub enum Type<'gc> {
 pub struct Struct<'gc>(Gc<'gc, RefCell<'gc, StructData<'gc>>>);
 
 impl<'gc> Struct<'gc> {
+    pub fn new(
+        arena: &Arena<'gc>,
+        ident: Ident<'gc>,
+        proto: StructProto<'gc>,
+    ) -> Struct<'gc> {
+        let data = StructData {
+            ident,
+            proto,
+            fns: BTreeMap::new(),
+        };
+        Struct(Gc::new(arena, RefCell::new(arena, data)))
+    }
+
     pub fn proto(&self) -> Ref<StructProto<'gc>> {
         Ref::map(self.0.borrow(), |data| &data.proto)
     }
 }
 
+/// Types are nominal, so they are only equal to themselves.
+impl<'gc> PartialEq for Struct<'gc> {
+    fn eq(&self, other: &Struct<'gc>) -> bool {
+        Gc::ptr_eq(&self.0, &other.0)
+    }
+}
+
 #[derive(Debug, Trace)]
 pub struct StructData<'gc> {
     ident: Ident<'gc>,
@@ -28,9 +49,48 @@ pub struct StructData<'gc> {
     fns: BTreeMap<Ident<'gc>, Fn<'gc>>,
 }
 
-#[derive(Debug, Trace)]
+#[derive(Clone, Debug, Trace)]
 pub struct Enum<'gc>(Gc<'gc, RefCell<'gc, EnumData<'gc>>>);
 
+impl<'gc> Enum<'gc> {
+    pub fn new(
+        arena: &Arena<'gc>,
+        ident: Ident<'gc>,
+        variants: Vec<EnumVariant<'gc>>,
+    ) -> Enum<'gc> {
+        let data = EnumData {
+            ident,
+            variants,
+            fns: BTreeMap::new(),
+        };
+        Enum(Gc::new(arena, RefCell::new(arena, data)))
+    }
+
+    pub fn variant(&self, variant: u8) -> Option<Ref<'_, EnumVariant<'gc>>> {
+        let data = self.0.borrow();
+        if (variant as usize) < data.variants.len() {
+            Some(Ref::map(data, |data| &data.variants[variant as usize]))
+        } else {
+            None
+        }
+    }
+
+    pub fn variant_index(&self, ident: &Ident<'gc>) -> Option<u8> {
+        self.0
+            .borrow()
+            .variants
+            .iter()
+            .position(|variant| variant.ident == *ident)
+            .and_then(|index| u8::try_from(index).ok())
+    }
+}
+
+impl<'gc> PartialEq for Enum<'gc> {
+    fn eq(&self, other: &Enum<'gc>) -> bool {
+        Gc::ptr_eq(&self.0, &other.0)
+    }
+}
+
 #[derive(Debug, Trace)]
 pub struct EnumData<'gc> {
     ident: Ident<'gc>,
@@ -44,6 +104,20 @@ pub struct EnumVariant<'gc> {
     proto: StructProto<'gc>,
 }
 
+impl<'gc> EnumVariant<'gc> {
+    pub fn new(ident: Ident<'gc>, proto: StructProto<'gc>) -> EnumVariant<'gc> {
+        EnumVariant { ident, proto }
+    }
+
+    pub fn ident(&self) -> &Ident<'gc> {
+        &self.ident
+    }
+
+    pub fn proto(&self) -> &StructProto<'gc> {
+        &self.proto
+    }
+}
+
 #[derive(Debug, Trace)]
 pub enum StructProto<'gc> {
     Tuple(u8),
@@ -56,6 +130,12 @@ pub struct MapData<'gc> {
 }
 
 impl<'gc> MapData<'gc> {
+    pub fn new(fields: Vec<Ident<'gc>>) -> MapData<'gc> {
+        MapData {
+            fields: fields.into_iter().map(|field| (field, ())).collect(),
+        }
+    }
+
     pub fn fields(&self) -> &BTreeMap<Ident<'gc>, ()> {
         &self.fields
     }
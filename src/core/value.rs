@@ -1,6 +1,12 @@
-use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter;
+use std::mem;
+use std::ptr;
 
-use eko_gc::{Arena, Gc, RefCell};
+use eko_gc::{Arena, Gc, Ref, RefCell};
 
 use crate::engine::frame::CapturedScope;
 
@@ -9,24 +15,478 @@ use super::fun::Fn;
 use super::ident::Ident;
 use super::typ::{self, Kind};
 
-#[derive(Clone, Debug, PartialEq, Trace)]
+/// How deeply comparisons look into nested values. Beyond it, values are
+/// only compared by identity, so that a value which contains itself can't
+/// be compared forever.
+const MAX_DEPTH: usize = 128;
+
+/// How many of the values nested in a value are hashed, for the same reason.
+const MAX_HASHED: usize = 64;
+
+#[derive(Clone, Debug, Trace)]
 pub enum Value<'gc> {
+    Unit,
     Boolean(bool),
     Integer(i64),
     Float(f64),
     String(String<'gc>),
     Tuple(Tuple<'gc>),
+    List(List<'gc>),
+    Map(Map<'gc>),
     Struct(Struct<'gc>),
     Enum(Enum<'gc>),
+    Fn(Fn<'gc>),
     Closure(Closure<'gc>),
 }
 
+impl<'gc> Value<'gc> {
+    /// Whether both values are the same object, or equal scalars of the same
+    /// kind. Unlike `==`, this never looks inside strings and collections.
+    pub fn identical(&self, other: &Value<'gc>) -> bool {
+        match (self, other) {
+            (Value::String(left), Value::String(right)) => left.ptr_eq(right),
+            (Value::Tuple(left), Value::Tuple(right)) => left.ptr_eq(right),
+            (Value::List(left), Value::List(right)) => left.ptr_eq(right),
+            (Value::Map(left), Value::Map(right)) => left.ptr_eq(right),
+            (Value::Struct(left), Value::Struct(right)) => left.ptr_eq(right),
+            (Value::Enum(left), Value::Enum(right)) => left.ptr_eq(right),
+            (left, right) => {
+                mem::discriminant(left) == mem::discriminant(right)
+                    && left == right
+            }
+        }
+    }
+
+    fn eq_at(&self, other: &Value<'gc>, depth: usize) -> bool {
+        if depth > MAX_DEPTH {
+            return self.identical(other);
+        }
+
+        match (self, other) {
+            (Value::Unit, Value::Unit) => true,
+            (Value::Boolean(left), Value::Boolean(right)) => left == right,
+            (Value::Integer(left), Value::Integer(right)) => left == right,
+            (Value::Float(left), Value::Float(right)) => left == right,
+            (Value::Integer(integer), Value::Float(float))
+            | (Value::Float(float), Value::Integer(integer)) => {
+                float_to_integer(*float) == Some(*integer)
+            }
+            (Value::String(left), Value::String(right)) => left == right,
+            (Value::Tuple(left), Value::Tuple(right)) => {
+                left.eq_at(right, depth)
+            }
+            (Value::List(left), Value::List(right)) => left.eq_at(right, depth),
+            (Value::Map(left), Value::Map(right)) => left.eq_at(right, depth),
+            (Value::Struct(left), Value::Struct(right)) => {
+                left.eq_at(right, depth)
+            }
+            (Value::Enum(left), Value::Enum(right)) => left.eq_at(right, depth),
+            (Value::Fn(left), Value::Fn(right)) => left == right,
+            (Value::Closure(left), Value::Closure(right)) => left == right,
+            _ => false,
+        }
+    }
+
+    /// Orders the values like `<`, failing on the first pair of values, at
+    /// any depth, that have no order. Unordered numbers, such as a `NaN`, are
+    /// `None` instead.
+    pub fn try_partial_cmp(
+        &self,
+        other: &Value<'gc>,
+    ) -> Result<'gc, Option<Ordering>> {
+        self.partial_cmp_at(other, 0)
+    }
+
+    fn partial_cmp_at(
+        &self,
+        other: &Value<'gc>,
+        depth: usize,
+    ) -> Result<'gc, Option<Ordering>> {
+        if depth > MAX_DEPTH {
+            return Ok(if self.identical(other) {
+                Some(Ordering::Equal)
+            } else {
+                None
+            });
+        }
+
+        let ordering = match (self, other) {
+            (Value::Unit, Value::Unit) => Some(Ordering::Equal),
+            (Value::Boolean(left), Value::Boolean(right)) => {
+                left.partial_cmp(right)
+            }
+            (Value::Integer(left), Value::Integer(right)) => {
+                left.partial_cmp(right)
+            }
+            (Value::Float(left), Value::Float(right)) => {
+                left.partial_cmp(right)
+            }
+            (Value::Integer(left), Value::Float(right)) => {
+                compare_integer_float(*left, *right)
+            }
+            (Value::Float(left), Value::Integer(right)) => {
+                compare_integer_float(*right, *left).map(Ordering::reverse)
+            }
+            (Value::String(left), Value::String(right)) => {
+                left.partial_cmp(right)
+            }
+            (Value::Tuple(left), Value::Tuple(right)) => values_partial_cmp(
+                &left.0.borrow().fields,
+                &right.0.borrow().fields,
+                depth,
+            )?,
+            (Value::List(left), Value::List(right)) => values_partial_cmp(
+                &left.0.borrow().values,
+                &right.0.borrow().values,
+                depth,
+            )?,
+            (left, right) => {
+                return Err(Error::UnorderedValues {
+                    left: ValueKind::from(left),
+                    right: ValueKind::from(right),
+                });
+            }
+        };
+        Ok(ordering)
+    }
+
+    /// Hashes the value and the values nested in it, in order, until
+    /// `budget` of them have been hashed.
+    fn hash_at<H: Hasher>(&self, state: &mut H, budget: &mut usize) {
+        // Integral floats equal integers, so they have to hash the same.
+        if let Value::Float(float) = self {
+            if let Some(integer) = float_to_integer(*float) {
+                return Value::Integer(integer).hash_at(state, budget);
+            }
+        }
+
+        if *budget == 0 {
+            return;
+        }
+        *budget -= 1;
+
+        mem::discriminant(self).hash(state);
+        match self {
+            Value::Unit => {}
+            Value::Boolean(boolean) => boolean.hash(state),
+            Value::Integer(integer) => integer.hash(state),
+            Value::Float(float) => float.to_bits().hash(state),
+            Value::String(string) => string.hash(state),
+            Value::Tuple(tuple) => {
+                hash_values(&tuple.0.borrow().fields, state, budget)
+            }
+            Value::List(list) => {
+                hash_values(&list.0.borrow().values, state, budget)
+            }
+            // Equal maps can hold their entries in different orders.
+            Value::Map(map) => map.len().hash(state),
+            Value::Struct(strukt) => {
+                strukt.0.borrow().proto.hash_at(state, budget)
+            }
+            Value::Enum(enu) => {
+                let data = enu.0.borrow();
+                data.variant.hash(state);
+                data.proto.hash_at(state, budget);
+            }
+            Value::Fn(fun) => fun.hash(state),
+            Value::Closure(closure) => ptr::hash(&*closure.0, state),
+        }
+    }
+}
+
+/// Values are compared structurally, and numbers by value across integers
+/// and floats.
+impl<'gc> PartialEq for Value<'gc> {
+    fn eq(&self, other: &Value<'gc>) -> bool {
+        self.eq_at(other, 0)
+    }
+}
+
+/// Values are ordered if they are units, booleans, numbers or strings, or
+/// tuples or lists of ordered values, compared lexicographically. Other values
+/// are only ordered against equal ones.
+impl<'gc> PartialOrd for Value<'gc> {
+    fn partial_cmp(&self, other: &Value<'gc>) -> Option<Ordering> {
+        match self.try_partial_cmp(other) {
+            Ok(ordering) => ordering,
+            Err(_) if self == other => Some(Ordering::Equal),
+            Err(_) => None,
+        }
+    }
+}
+
+/// Hashes consistently with `==`. Functions and closures hash by identity.
+impl<'gc> Hash for Value<'gc> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut budget = MAX_HASHED;
+        self.hash_at(state, &mut budget);
+    }
+}
+
+fn values_eq<'gc>(
+    left: &[Value<'gc>],
+    right: &[Value<'gc>],
+    depth: usize,
+) -> bool {
+    left.len() == right.len()
+        && left
+            .iter()
+            .zip(right)
+            .all(|(left, right)| left.eq_at(right, depth + 1))
+}
+
+fn values_partial_cmp<'gc>(
+    left: &[Value<'gc>],
+    right: &[Value<'gc>],
+    depth: usize,
+) -> Result<'gc, Option<Ordering>> {
+    for (left, right) in left.iter().zip(right) {
+        match left.partial_cmp_at(right, depth + 1)? {
+            Some(Ordering::Equal) => {}
+            ordering => return Ok(ordering),
+        }
+    }
+    Ok(left.len().partial_cmp(&right.len()))
+}
+
+fn hash_values<'gc, H: Hasher>(
+    values: &[Value<'gc>],
+    state: &mut H,
+    budget: &mut usize,
+) {
+    values.len().hash(state);
+    for value in values {
+        value.hash_at(state, budget);
+    }
+}
+
+/// The integer equal to `float`, if there is one.
+fn float_to_integer(float: f64) -> Option<i64> {
+    // `i64::MIN` converts exactly, but `i64::MAX` rounds up to 2^63, which is
+    // out of range.
+    if float.fract() == 0.0
+        && float >= i64::MIN as f64
+        && float < i64::MAX as f64
+    {
+        Some(float as i64)
+    } else {
+        None
+    }
+}
+
+/// Compares an integer and a float exactly, rather than after rounding the
+/// integer to a float.
+fn compare_integer_float(integer: i64, float: f64) -> Option<Ordering> {
+    if let Some(float) = float_to_integer(float) {
+        Some(integer.cmp(&float))
+    } else if float.is_nan() {
+        None
+    } else if float >= i64::MAX as f64 {
+        Some(Ordering::Less)
+    } else if float < i64::MIN as f64 {
+        Some(Ordering::Greater)
+    } else {
+        // A fractional float is small enough that the integer converts
+        // exactly wherever it could matter.
+        (integer as f64).partial_cmp(&float)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ValueKind {
+    Unit,
+    Boolean,
+    Integer,
+    Float,
+    String,
+    Tuple,
+    List,
+    Map,
+    Struct,
+    Enum,
+    Fn,
+    Closure,
+}
+
+impl<'gc> From<&Value<'gc>> for ValueKind {
+    fn from(value: &Value<'gc>) -> ValueKind {
+        use self::ValueKind::*;
+
+        match value {
+            Value::Unit => Unit,
+            Value::Boolean(_) => Boolean,
+            Value::Integer(_) => Integer,
+            Value::Float(_) => Float,
+            Value::String(_) => String,
+            Value::Tuple(_) => Tuple,
+            Value::List(_) => List,
+            Value::Map(_) => Map,
+            Value::Struct(_) => Struct,
+            Value::Enum(_) => Enum,
+            Value::Fn(_) => Fn,
+            Value::Closure(_) => Closure,
+        }
+    }
+}
+
+impl fmt::Display for ValueKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::ValueKind::*;
+
+        match self {
+            Unit => write!(f, "unit"),
+            Boolean => write!(f, "boolean"),
+            Integer => write!(f, "integer"),
+            Float => write!(f, "float"),
+            String => write!(f, "string"),
+            Tuple => write!(f, "tuple"),
+            List => write!(f, "list"),
+            Map => write!(f, "map"),
+            Struct => write!(f, "struct"),
+            Enum => write!(f, "enum"),
+            Fn => write!(f, "function"),
+            Closure => write!(f, "closure"),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Trace)]
 pub struct String<'gc>(Gc<'gc, RefCell<'gc, std::string::String>>);
 
+impl<'gc> String<'gc> {
+    pub fn new(arena: &Arena<'gc>, string: std::string::String) -> String<'gc> {
+        String(Gc::new(arena, RefCell::new(arena, string)))
+    }
+
+    pub fn from_str(arena: &Arena<'gc>, string: &str) -> String<'gc> {
+        String::new(arena, string.to_string())
+    }
+
+    pub fn string(&self) -> Ref<'_, std::string::String> {
+        self.0.borrow()
+    }
+
+    pub fn ptr_eq(&self, other: &String<'gc>) -> bool {
+        Gc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// The number of chars in the string, which is what scripts index by.
+    pub fn char_len(&self) -> usize {
+        self.0.borrow().chars().count()
+    }
+
+    pub fn byte_len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    pub fn concat(
+        &self,
+        arena: &Arena<'gc>,
+        other: &String<'gc>,
+    ) -> String<'gc> {
+        let mut string = self.0.borrow().clone();
+        string.push_str(&other.0.borrow());
+        String::new(arena, string)
+    }
+
+    /// The char at `index`, returning `None` if it is out of bounds.
+    pub fn char_at(
+        &self,
+        arena: &Arena<'gc>,
+        index: usize,
+    ) -> Option<String<'gc>> {
+        let ch = self.0.borrow().chars().nth(index)?;
+        Some(String::new(arena, ch.to_string()))
+    }
+
+    /// Copies the chars from `start` up to `end` into a new string, returning
+    /// `None` if the range is out of bounds.
+    pub fn slice(
+        &self,
+        arena: &Arena<'gc>,
+        start: usize,
+        end: usize,
+    ) -> Option<String<'gc>> {
+        if start > end {
+            return None;
+        }
+
+        let string = self.0.borrow();
+        let byte_index = |index| {
+            string
+                .char_indices()
+                .map(|(byte_index, _)| byte_index)
+                .chain(iter::once(string.len()))
+                .nth(index)
+        };
+        let slice = &string[byte_index(start)?..byte_index(end)?];
+        Some(String::from_str(arena, slice))
+    }
+
+    /// The char index of the first occurrence of `pattern`.
+    pub fn find(&self, pattern: &str) -> Option<usize> {
+        let string = self.0.borrow();
+        let byte_index = string.find(pattern)?;
+        Some(string[..byte_index].chars().count())
+    }
+
+    pub fn contains(&self, pattern: &str) -> bool {
+        self.0.borrow().contains(pattern)
+    }
+
+    pub fn replace(
+        &self,
+        arena: &Arena<'gc>,
+        from: &str,
+        to: &str,
+    ) -> String<'gc> {
+        String::new(arena, self.0.borrow().replace(from, to))
+    }
+
+    pub fn split(
+        &self,
+        arena: &Arena<'gc>,
+        separator: &str,
+    ) -> Vec<String<'gc>> {
+        self.0
+            .borrow()
+            .split(separator)
+            .map(|part| String::from_str(arena, part))
+            .collect()
+    }
+
+    pub fn to_uppercase(&self, arena: &Arena<'gc>) -> String<'gc> {
+        String::new(arena, self.0.borrow().to_uppercase())
+    }
+
+    pub fn to_lowercase(&self, arena: &Arena<'gc>) -> String<'gc> {
+        String::new(arena, self.0.borrow().to_lowercase())
+    }
+}
+
 impl<'gc> PartialEq for String<'gc> {
     fn eq(&self, other: &String<'gc>) -> bool {
-        Gc::ptr_eq(&self.0, &other.0)
+        *self.string() == *other.string()
+    }
+}
+
+impl<'gc> Eq for String<'gc> {}
+
+impl<'gc> PartialOrd for String<'gc> {
+    fn partial_cmp(&self, other: &String<'gc>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'gc> Ord for String<'gc> {
+    fn cmp(&self, other: &String<'gc>) -> Ordering {
+        self.string().cmp(&other.string())
+    }
+}
+
+impl<'gc> Hash for String<'gc> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.string().hash(state);
     }
 }
 
@@ -45,11 +505,26 @@ impl<'gc> Tuple<'gc> {
     pub fn field(&self, field: u8) -> Result<'gc, Value<'gc>> {
         self.0.borrow().field(field)
     }
+
+    pub fn ptr_eq(&self, other: &Tuple<'gc>) -> bool {
+        Gc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<'gc> Tuple<'gc> {
+    fn eq_at(&self, other: &Tuple<'gc>, depth: usize) -> bool {
+        self.ptr_eq(other)
+            || values_eq(
+                &self.0.borrow().fields,
+                &other.0.borrow().fields,
+                depth,
+            )
+    }
 }
 
 impl<'gc> PartialEq for Tuple<'gc> {
     fn eq(&self, other: &Tuple<'gc>) -> bool {
-        Gc::ptr_eq(&self.0, &other.0)
+        self.eq_at(other, 0)
     }
 }
 
@@ -79,6 +554,281 @@ impl<'gc> TupleData<'gc> {
     }
 }
 
+#[derive(Clone, Debug, Trace)]
+pub struct List<'gc>(Gc<'gc, RefCell<'gc, ListData<'gc>>>);
+
+impl<'gc> List<'gc> {
+    pub fn new(arena: &Arena<'gc>, values: Vec<Value<'gc>>) -> List<'gc> {
+        List(Gc::new(arena, RefCell::new(arena, ListData { values })))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().values.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Value<'gc>> {
+        self.0.borrow().values.get(index).cloned()
+    }
+
+    /// Replaces the value at `index`, returning `None` if it is out of
+    /// bounds.
+    pub fn set(&self, index: usize, value: Value<'gc>) -> Option<()> {
+        *self.0.borrow_mut().values.get_mut(index)? = value;
+        Some(())
+    }
+
+    pub fn push(&self, value: Value<'gc>) {
+        self.0.borrow_mut().values.push(value);
+    }
+
+    pub fn pop(&self) -> Option<Value<'gc>> {
+        self.0.borrow_mut().values.pop()
+    }
+
+    /// Copies the values from `start` up to `end` into a new list, returning
+    /// `None` if the range is out of bounds.
+    pub fn slice(
+        &self,
+        arena: &Arena<'gc>,
+        start: usize,
+        end: usize,
+    ) -> Option<List<'gc>> {
+        let values = self.0.borrow().values.get(start..end)?.to_vec();
+        Some(List::new(arena, values))
+    }
+
+    pub fn ptr_eq(&self, other: &List<'gc>) -> bool {
+        Gc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<'gc> List<'gc> {
+    fn eq_at(&self, other: &List<'gc>, depth: usize) -> bool {
+        self.ptr_eq(other)
+            || values_eq(
+                &self.0.borrow().values,
+                &other.0.borrow().values,
+                depth,
+            )
+    }
+}
+
+impl<'gc> PartialEq for List<'gc> {
+    fn eq(&self, other: &List<'gc>) -> bool {
+        self.eq_at(other, 0)
+    }
+}
+
+#[derive(Debug, Trace)]
+pub struct ListData<'gc> {
+    values: Vec<Value<'gc>>,
+}
+
+/// A map from values to values, which iterates in insertion order.
+#[derive(Clone, Debug, Trace)]
+pub struct Map<'gc>(Gc<'gc, RefCell<'gc, MapEntries<'gc>>>);
+
+impl<'gc> Map<'gc> {
+    pub fn new(arena: &Arena<'gc>) -> Map<'gc> {
+        let entries = MapEntries {
+            entries: Vec::new(),
+            indices: HashMap::new(),
+        };
+        Map(Gc::new(arena, RefCell::new(arena, entries)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().indices.is_empty()
+    }
+
+    pub fn get(&self, key: &Value<'gc>) -> Result<'gc, Option<Value<'gc>>> {
+        let key = Key::new(key)?;
+        let entries = self.0.borrow();
+        Ok(entries
+            .indices
+            .get(&key)
+            .map(|&index| entries.value(index).clone()))
+    }
+
+    pub fn contains(&self, key: &Value<'gc>) -> Result<'gc, bool> {
+        let key = Key::new(key)?;
+        Ok(self.0.borrow().indices.contains_key(&key))
+    }
+
+    /// Inserts `value` under `key`, returning the value it replaced. A
+    /// replaced entry keeps its place in the iteration order.
+    pub fn insert(
+        &self,
+        key: Value<'gc>,
+        value: Value<'gc>,
+    ) -> Result<'gc, Option<Value<'gc>>> {
+        let hash_key = Key::new(&key)?;
+        let mut entries = self.0.borrow_mut();
+
+        if let Some(&index) = entries.indices.get(&hash_key) {
+            let old_value = mem::replace(entries.value_mut(index), value);
+            return Ok(Some(old_value));
+        }
+
+        let index = entries.entries.len();
+        entries.entries.push(Some((key, value)));
+        entries.indices.insert(hash_key, index);
+        Ok(None)
+    }
+
+    pub fn remove(&self, key: &Value<'gc>) -> Result<'gc, Option<Value<'gc>>> {
+        let key = Key::new(key)?;
+        let mut entries = self.0.borrow_mut();
+
+        let index = match entries.indices.remove(&key) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let (_, value) = entries.entries[index].take().unwrap();
+        // Removed entries are left as holes until they outnumber the live
+        // ones, so that removing stays constant time on average.
+        if entries.entries.len() > 2 * entries.indices.len() {
+            entries.compact();
+        }
+        Ok(Some(value))
+    }
+
+    pub fn keys(&self) -> Vec<Value<'gc>> {
+        let entries = self.0.borrow();
+        entries.iter().map(|(key, _)| key.clone()).collect()
+    }
+
+    pub fn entries(&self) -> Vec<(Value<'gc>, Value<'gc>)> {
+        self.0.borrow().iter().cloned().collect()
+    }
+
+    pub fn ptr_eq(&self, other: &Map<'gc>) -> bool {
+        Gc::ptr_eq(&self.0, &other.0)
+    }
+
+    fn eq_at(&self, other: &Map<'gc>, depth: usize) -> bool {
+        if self.ptr_eq(other) {
+            return true;
+        }
+
+        self.len() == other.len()
+            && self
+                .entries()
+                .iter()
+                .all(|(key, value)| match other.get(key) {
+                    Ok(Some(other_value)) => {
+                        value.eq_at(&other_value, depth + 1)
+                    }
+                    _ => false,
+                })
+    }
+}
+
+/// Maps are equal if they have equal values under the same keys, in any
+/// order.
+impl<'gc> PartialEq for Map<'gc> {
+    fn eq(&self, other: &Map<'gc>) -> bool {
+        self.eq_at(other, 0)
+    }
+}
+
+#[derive(Debug, Trace)]
+pub struct MapEntries<'gc> {
+    // The entries in insertion order, with `None` in place of removed ones,
+    // and where each key is among them.
+    entries: Vec<Option<(Value<'gc>, Value<'gc>)>>,
+    indices: HashMap<Key, usize>,
+}
+
+impl<'gc> MapEntries<'gc> {
+    fn iter(&self) -> impl Iterator<Item = &(Value<'gc>, Value<'gc>)> {
+        self.entries.iter().flatten()
+    }
+
+    fn value(&self, index: usize) -> &Value<'gc> {
+        &self.entries[index].as_ref().unwrap().1
+    }
+
+    fn value_mut(&mut self, index: usize) -> &mut Value<'gc> {
+        &mut self.entries[index].as_mut().unwrap().1
+    }
+
+    /// Drops the holes left by removed entries.
+    fn compact(&mut self) {
+        let mut new_indices = Vec::with_capacity(self.entries.len());
+        let mut len = 0;
+        for entry in &self.entries {
+            new_indices.push(len);
+            if entry.is_some() {
+                len += 1;
+            }
+        }
+
+        self.entries.retain(Option::is_some);
+        for index in self.indices.values_mut() {
+            *index = new_indices[*index];
+        }
+    }
+}
+
+/// A snapshot of a key used to hash it, so that mutating a tuple after using
+/// it as a key can't move its entry. Integral floats are snapshotted as the
+/// integers they equal.
+#[derive(Debug, Eq, Hash, PartialEq, Trace)]
+enum Key {
+    Boolean(bool),
+    Integer(i64),
+    Float(u64),
+    String(std::string::String),
+    Tuple(Vec<Key>),
+}
+
+impl Key {
+    fn new<'gc>(value: &Value<'gc>) -> Result<'gc, Key> {
+        Key::new_at(value, 0)
+    }
+
+    fn new_at<'gc>(value: &Value<'gc>, depth: usize) -> Result<'gc, Key> {
+        if depth > MAX_DEPTH {
+            return Err(Error::NestingTooDeep {
+                max_depth: MAX_DEPTH,
+            });
+        }
+
+        match value {
+            Value::Boolean(boolean) => Ok(Key::Boolean(*boolean)),
+            Value::Integer(integer) => Ok(Key::Integer(*integer)),
+            // `NaN` isn't equal to itself, so it could never be found again.
+            Value::Float(float) if !float.is_nan() => {
+                match float_to_integer(*float) {
+                    Some(integer) => Ok(Key::Integer(integer)),
+                    None => Ok(Key::Float(float.to_bits())),
+                }
+            }
+            Value::String(string) => Ok(Key::String(string.string().clone())),
+            Value::Tuple(tuple) => {
+                let fields = &tuple.0.borrow().fields;
+                let keys = fields
+                    .iter()
+                    .map(|field| Key::new_at(field, depth + 1))
+                    .collect::<Result<_>>()?;
+                Ok(Key::Tuple(keys))
+            }
+            value => Err(Error::UnhashableKey {
+                kind: ValueKind::from(value),
+            }),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Trace)]
 pub struct Struct<'gc>(Gc<'gc, RefCell<'gc, StructData<'gc>>>);
 
@@ -146,11 +896,25 @@ impl<'gc> Struct<'gc> {
     pub fn map_field(&self, field: Ident<'gc>) -> Result<'gc, Value<'gc>> {
         self.0.borrow().proto.map_field(field)
     }
+
+    pub fn ptr_eq(&self, other: &Struct<'gc>) -> bool {
+        Gc::ptr_eq(&self.0, &other.0)
+    }
+
+    fn eq_at(&self, other: &Struct<'gc>, depth: usize) -> bool {
+        if self.ptr_eq(other) {
+            return true;
+        }
+
+        let (left, right) = (self.0.borrow(), other.0.borrow());
+        left.typ == right.typ && left.proto.eq_at(&right.proto, depth)
+    }
 }
 
+/// Structs are equal if they have the same type and equal fields.
 impl<'gc> PartialEq for Struct<'gc> {
     fn eq(&self, other: &Struct<'gc>) -> bool {
-        Gc::ptr_eq(&self.0, &other.0)
+        self.eq_at(other, 0)
     }
 }
 
@@ -164,6 +928,60 @@ pub struct StructData<'gc> {
 pub struct Enum<'gc>(Gc<'gc, RefCell<'gc, EnumData<'gc>>>);
 
 impl<'gc> Enum<'gc> {
+    pub fn new_tuple(
+        arena: &Arena<'gc>,
+        typ: typ::Enum<'gc>,
+        variant: u8,
+        fields: Vec<Value<'gc>>,
+    ) -> Result<'gc, Enum<'gc>> {
+        let proto = match typ.variant(variant).as_deref().map(|v| v.proto()) {
+            Some(typ::StructProto::Tuple(num_fields)) => {
+                StructProto::new_tuple(*num_fields, fields)?
+            }
+            Some(typ::StructProto::Map(_)) => {
+                return Err(Error::InvalidKind {
+                    expected: Kind::Tuple,
+                    received: Kind::Map,
+                });
+            }
+            None => return Err(Error::InvalidVariant { variant }),
+        };
+
+        let data = EnumData {
+            typ,
+            variant,
+            proto,
+        };
+        Ok(Enum(Gc::new(arena, RefCell::new(arena, data))))
+    }
+
+    pub fn new_map(
+        arena: &Arena<'gc>,
+        typ: typ::Enum<'gc>,
+        variant: u8,
+        fields: BTreeMap<Ident<'gc>, Value<'gc>>,
+    ) -> Result<'gc, Enum<'gc>> {
+        let proto = match typ.variant(variant).as_deref().map(|v| v.proto()) {
+            Some(typ::StructProto::Tuple(_)) => {
+                return Err(Error::InvalidKind {
+                    expected: Kind::Map,
+                    received: Kind::Tuple,
+                });
+            }
+            Some(typ::StructProto::Map(map_data)) => {
+                StructProto::new_map(map_data, fields)?
+            }
+            None => return Err(Error::InvalidVariant { variant }),
+        };
+
+        let data = EnumData {
+            typ,
+            variant,
+            proto,
+        };
+        Ok(Enum(Gc::new(arena, RefCell::new(arena, data))))
+    }
+
     pub fn set_tuple_field(
         &self,
         field: u8,
@@ -187,11 +1005,28 @@ impl<'gc> Enum<'gc> {
     pub fn map_field(&self, field: Ident<'gc>) -> Result<'gc, Value<'gc>> {
         self.0.borrow().proto.map_field(field)
     }
+
+    pub fn ptr_eq(&self, other: &Enum<'gc>) -> bool {
+        Gc::ptr_eq(&self.0, &other.0)
+    }
+
+    fn eq_at(&self, other: &Enum<'gc>, depth: usize) -> bool {
+        if self.ptr_eq(other) {
+            return true;
+        }
+
+        let (left, right) = (self.0.borrow(), other.0.borrow());
+        left.typ == right.typ
+            && left.variant == right.variant
+            && left.proto.eq_at(&right.proto, depth)
+    }
 }
 
+/// Enums are equal if they are the same variant of the same type, with equal
+/// fields.
 impl<'gc> PartialEq for Enum<'gc> {
     fn eq(&self, other: &Enum<'gc>) -> bool {
-        Gc::ptr_eq(&self.0, &other.0)
+        self.eq_at(other, 0)
     }
 }
 
@@ -209,6 +1044,39 @@ pub enum StructProto<'gc> {
 }
 
 impl<'gc> StructProto<'gc> {
+    fn eq_at(&self, other: &StructProto<'gc>, depth: usize) -> bool {
+        match (self, other) {
+            (StructProto::Tuple(left), StructProto::Tuple(right)) => {
+                values_eq(&left.fields, &right.fields, depth)
+            }
+            (StructProto::Map(left), StructProto::Map(right)) => {
+                left.fields.len() == right.fields.len()
+                    && left.fields.iter().zip(&right.fields).all(
+                        |((left_ident, left), (right_ident, right))| {
+                            left_ident == right_ident
+                                && left.eq_at(right, depth + 1)
+                        },
+                    )
+            }
+            _ => false,
+        }
+    }
+
+    fn hash_at<H: Hasher>(&self, state: &mut H, budget: &mut usize) {
+        match self {
+            StructProto::Tuple(tuple_data) => {
+                hash_values(&tuple_data.fields, state, budget)
+            }
+            // The field names are fixed by the type, so only the values are
+            // hashed.
+            StructProto::Map(map_data) => {
+                for value in map_data.fields.values() {
+                    value.hash_at(state, budget);
+                }
+            }
+        }
+    }
+
     fn new_tuple(
         num_fields: u8,
         fields: Vec<Value<'gc>>,
@@ -333,6 +1201,24 @@ impl<'gc> MapData<'gc> {
 pub struct Closure<'gc>(Gc<'gc, ClosureData<'gc>>);
 
 impl<'gc> Closure<'gc> {
+    pub fn new(
+        arena: &Arena<'gc>,
+        fun: Fn<'gc>,
+        captured_scope: CapturedScope<'gc>,
+    ) -> Closure<'gc> {
+        Closure(Gc::new(
+            arena,
+            ClosureData {
+                captured_scope,
+                data: fun,
+            },
+        ))
+    }
+
+    pub fn fun(&self) -> Fn<'gc> {
+        self.0.data.clone()
+    }
+
     pub fn captured_scope(&self) -> CapturedScope<'gc> {
         self.0.captured_scope.clone()
     }
@@ -349,3 +1235,210 @@ pub struct ClosureData<'gc> {
     captured_scope: CapturedScope<'gc>,
     data: Fn<'gc>,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    use eko_gc::Arena;
+
+    use crate::core::error::Error;
+
+    use super::{List, Map, String, Tuple, Value, ValueKind, MAX_DEPTH};
+
+    fn hash(value: &Value) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn structural_equality() {
+        let arena = Arena::new();
+        let string = |string: &str| {
+            Value::String(String::new(&arena, string.to_string()))
+        };
+        let tuple = |fields| Value::Tuple(Tuple::new(&arena, fields));
+
+        let left = tuple(vec![string("a"), Value::Integer(1)]);
+        let right = tuple(vec![string("a"), Value::Integer(1)]);
+        assert_eq!(left, right);
+        assert!(!left.identical(&right));
+        assert!(left.identical(&left.clone()));
+        assert_eq!(hash(&left), hash(&right));
+        let coerced = tuple(vec![string("a"), Value::Float(1.0)]);
+        assert_eq!(left, coerced);
+        assert_eq!(hash(&left), hash(&coerced));
+        assert_ne!(left, tuple(vec![string("a"), Value::Float(1.5)]));
+
+        let list = Value::List(List::new(&arena, vec![left.clone()]));
+        assert_eq!(list, Value::List(List::new(&arena, vec![right.clone()])));
+        assert_ne!(list, left);
+
+        let first = Map::new(&arena);
+        first.insert(string("x"), Value::Integer(1)).unwrap();
+        first.insert(string("y"), Value::Integer(2)).unwrap();
+        let second = Map::new(&arena);
+        second.insert(string("y"), Value::Integer(2)).unwrap();
+        second.insert(string("x"), Value::Integer(1)).unwrap();
+        let (first, second) = (Value::Map(first), Value::Map(second));
+        assert_eq!(first, second);
+        assert_eq!(hash(&first), hash(&second));
+
+        assert_eq!(Value::Float(0.0), Value::Float(-0.0));
+        assert_eq!(hash(&Value::Float(0.0)), hash(&Value::Float(-0.0)));
+        assert_ne!(Value::Float(f64::NAN), Value::Float(f64::NAN));
+    }
+
+    #[test]
+    fn ordering() {
+        let arena = Arena::new();
+        let string = |string: &str| {
+            Value::String(String::new(&arena, string.to_string()))
+        };
+        let list = |values| Value::List(List::new(&arena, values));
+
+        assert!(string("apple") < string("banana"));
+        assert!(
+            list(vec![Value::Integer(1), Value::Integer(2)])
+                < list(vec![Value::Integer(1), Value::Integer(3)])
+        );
+        assert!(
+            list(vec![Value::Integer(1)])
+                < list(vec![Value::Integer(1), Value::Unit])
+        );
+        assert_eq!(
+            list(vec![string("a")]).partial_cmp(&list(vec![string("a")])),
+            Some(Ordering::Equal),
+        );
+
+        assert_eq!(
+            Value::Integer(1).partial_cmp(&Value::Float(2.0)),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            Value::Integer(i64::MAX)
+                .partial_cmp(&Value::Float(i64::MAX as f64)),
+            Some(Ordering::Less)
+        );
+        assert!(
+            list(vec![Value::Float(1.0)])
+                < list(vec![Value::Integer(1), Value::Unit])
+        );
+        assert_eq!(string("a").partial_cmp(&Value::Integer(1)), None);
+        assert_eq!(
+            Value::Unit.partial_cmp(&Value::Unit),
+            Some(Ordering::Equal)
+        );
+
+        let map = Value::Map(Map::new(&arena));
+        assert_eq!(
+            list(vec![map.clone()]).partial_cmp(&list(vec![map.clone()])),
+            Some(Ordering::Equal)
+        );
+        match list(vec![map.clone()]).try_partial_cmp(&list(vec![map])) {
+            Err(Error::UnorderedValues {
+                left: ValueKind::Map,
+                right: ValueKind::Map,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(
+            list(vec![Value::Integer(1), Value::Unit])
+                .try_partial_cmp(&list(vec![Value::Integer(2), string("a")]))
+                .unwrap(),
+            Some(Ordering::Less)
+        );
+    }
+
+    #[test]
+    fn self_referential() {
+        let arena = Arena::new();
+
+        let left = List::new(&arena, Vec::new());
+        left.push(Value::List(left.clone()));
+        let right = List::new(&arena, Vec::new());
+        right.push(Value::List(right.clone()));
+        let (left, right) = (Value::List(left), Value::List(right));
+        assert_eq!(left, left.clone());
+        assert_ne!(left, right);
+        assert_eq!(left.partial_cmp(&right), None);
+        assert_eq!(hash(&left), hash(&right));
+
+        let tuple = Tuple::new(&arena, vec![Value::Unit]);
+        tuple.set_field(0, Value::Tuple(tuple.clone())).unwrap();
+        let other = Tuple::new(&arena, vec![Value::Unit]);
+        other.set_field(0, Value::Tuple(other.clone())).unwrap();
+        let (tuple, other) = (Value::Tuple(tuple), Value::Tuple(other));
+        assert_ne!(tuple, other);
+        assert_eq!(tuple.partial_cmp(&other), None);
+        assert_eq!(hash(&tuple), hash(&other));
+
+        let map = Map::new(&arena);
+        match map.insert(tuple, Value::Unit) {
+            Err(Error::NestingTooDeep {
+                max_depth: MAX_DEPTH,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+        match map.insert(left, Value::Unit) {
+            Err(Error::UnhashableKey { .. }) => {}
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn map_remove() {
+        let arena = Arena::new();
+        let map = Map::new(&arena);
+        for key in 0..6 {
+            map.insert(Value::Integer(key), Value::Integer(key * 10))
+                .unwrap();
+        }
+
+        assert_eq!(
+            map.remove(&Value::Integer(1)).unwrap(),
+            Some(Value::Integer(10))
+        );
+        assert_eq!(map.remove(&Value::Integer(1)).unwrap(), None);
+        map.insert(Value::Integer(1), Value::Unit).unwrap();
+        // Enough removals to drop the holes they leave.
+        for key in &[0, 2, 4, 5] {
+            map.remove(&Value::Integer(*key)).unwrap();
+        }
+        map.insert(Value::Integer(3), Value::Integer(0)).unwrap();
+        map.insert(Value::Integer(6), Value::Integer(60)).unwrap();
+
+        assert_eq!(map.len(), 3);
+        assert_eq!(
+            map.entries(),
+            vec![
+                (Value::Integer(3), Value::Integer(0)),
+                (Value::Integer(1), Value::Unit),
+                (Value::Integer(6), Value::Integer(60)),
+            ]
+        );
+        assert_eq!(map.get(&Value::Integer(1)).unwrap(), Some(Value::Unit));
+        assert_eq!(map.get(&Value::Integer(2)).unwrap(), None);
+    }
+
+    #[test]
+    fn string_slice() {
+        let arena = Arena::new();
+        let string = String::new(&arena, "héllo wörld".to_string());
+        let slice = |start, end| {
+            string
+                .slice(&arena, start, end)
+                .map(|slice| slice.string().clone())
+        };
+
+        // Indices count chars, so they never split a multi-byte one.
+        assert_eq!(slice(1, 2), Some("é".to_string()));
+        assert_eq!(slice(2, 8), Some("llo wö".to_string()));
+        assert_eq!(slice(11, 11), Some(std::string::String::new()));
+        assert_eq!(slice(0, 12), None);
+        assert_eq!(slice(3, 2), None);
+    }
+}
@@ -1,4 +1,9 @@
+use std::fmt;
+
+use crate::core::error::Error as CoreError;
 use crate::core::ident::Ident;
+use crate::core::typ::Kind;
+use crate::core::value::ValueKind;
 
 use super::machine::OperandKind;
 
@@ -9,6 +14,9 @@ pub enum Error<'gc> {
     #[error(display = "method not found: {}", ident)]
     MethodNotFound { ident: Ident<'gc> },
 
+    #[error(display = "function not found: {}", ident)]
+    FnNotFound { ident: Ident<'gc> },
+
     #[error(
         display = "wrong arity: expected {}, received {}",
         expected,
@@ -19,6 +27,27 @@ pub enum Error<'gc> {
     #[error(display = "empty operand stack")]
     EmptyOperandStack,
 
+    #[error(display = "empty call stack")]
+    EmptyCallStack,
+
+    #[error(display = "maximum call depth of {} exceeded", max_call_depth)]
+    CallDepthExceeded { max_call_depth: usize },
+
+    #[error(display = "heap limit of {} bytes exceeded", max_heap_size)]
+    OutOfMemory { max_heap_size: usize },
+
+    #[error(display = "out of fuel")]
+    OutOfFuel,
+
+    #[error(display = "interrupted")]
+    Interrupted,
+
+    #[error(display = "no suspended call to resume")]
+    NoSuspendedCall,
+
+    #[error(display = "frame left {} operands, expected 1", len)]
+    LeftoverOperands { len: usize },
+
     #[error(
         display = "invalid operand kind: expected {}, received {}",
         expected,
@@ -29,9 +58,212 @@ pub enum Error<'gc> {
         received: OperandKind,
     },
 
+    #[error(
+        display = "invalid value kind: expected {}, received {}",
+        expected,
+        received
+    )]
+    InvalidValueKind {
+        expected: ValueKind,
+        received: ValueKind,
+    },
+
+    #[error(
+        display = "unsupported operands for `{}`: {} and {}",
+        op,
+        left,
+        right
+    )]
+    UnsupportedOperands {
+        op: &'static str,
+        left: ValueKind,
+        right: ValueKind,
+    },
+
+    #[error(display = "unsupported operand for `{}`: {}", op, operand)]
+    UnsupportedOperand {
+        op: &'static str,
+        operand: ValueKind,
+    },
+
+    #[error(display = "division by zero")]
+    DivisionByZero,
+
+    #[error(display = "integer overflow in `{}`", op)]
+    IntegerOverflow { op: &'static str },
+
+    #[error(display = "negative exponent: {}", exponent)]
+    NegativeExponent { exponent: i64 },
+
+    #[error(display = "invalid shift amount: {}", amount)]
+    InvalidShift { amount: i64 },
+
+    #[error(display = "missing field: {}", field)]
+    MissingField { field: Ident<'gc> },
+
+    #[error(display = "invalid field: {}", field)]
+    InvalidField { field: Ident<'gc> },
+
+    #[error(
+        display = "invalid kind: expected {}, received {}",
+        expected,
+        received
+    )]
+    InvalidKind { expected: Kind, received: Kind },
+
+    #[error(display = "invalid variant: {}", variant)]
+    InvalidVariant { variant: u8 },
+
+    #[error(display = "index out of bounds: {} (length {})", index, len)]
+    IndexOutOfBounds { index: i64, len: usize },
+
+    #[error(display = "invalid slice: {}..{} (length {})", start, end, len)]
+    InvalidSlice { start: i64, end: i64, len: usize },
+
+    #[error(display = "empty list")]
+    EmptyList,
+
+    #[error(display = "empty separator")]
+    EmptySeparator,
+
+    #[error(display = "unhashable key: {}", kind)]
+    UnhashableKey { kind: ValueKind },
+
+    #[error(display = "value nested more than {} deep", max_depth)]
+    NestingTooDeep { max_depth: usize },
+
+    #[error(display = "unordered values: {} and {}", left, right)]
+    UnorderedValues { left: ValueKind, right: ValueKind },
+
+    #[error(display = "key not found")]
+    KeyNotFound,
+
+    #[error(display = "invalid jump: {}", index)]
+    InvalidJump { index: usize },
+
     #[error(display = "parent not found")]
     ParentNotFound,
 
     #[error(display = "variable not found: {}", var)]
     VarNotFound { var: usize },
+
+    #[error(display = "uninitialized variable: {}", var)]
+    UninitializedVar { var: usize },
+}
+
+impl<'gc> From<CoreError<'gc>> for Error<'gc> {
+    fn from(error: CoreError<'gc>) -> Error<'gc> {
+        match error {
+            CoreError::MissingField { field } => Error::MissingField { field },
+            CoreError::InvalidField { field } => Error::InvalidField { field },
+            CoreError::InvalidKind { expected, received } => {
+                Error::InvalidKind { expected, received }
+            }
+            CoreError::InvalidVariant { variant } => {
+                Error::InvalidVariant { variant }
+            }
+            CoreError::UnhashableKey { kind } => Error::UnhashableKey { kind },
+            CoreError::NestingTooDeep { max_depth } => {
+                Error::NestingTooDeep { max_depth }
+            }
+            CoreError::UnorderedValues { left, right } => {
+                Error::UnorderedValues { left, right }
+            }
+        }
+    }
+}
+
+/// An error from a call along with the stack trace at the point it failed.
+#[derive(Debug)]
+pub struct CallError<'gc> {
+    error: Error<'gc>,
+    stack_trace: Vec<StackFrame<'gc>>,
+}
+
+impl<'gc> CallError<'gc> {
+    pub fn new(
+        error: Error<'gc>,
+        stack_trace: Vec<StackFrame<'gc>>,
+    ) -> CallError<'gc> {
+        CallError { error, stack_trace }
+    }
+
+    pub fn error(&self) -> &Error<'gc> {
+        &self.error
+    }
+
+    pub fn into_error(self) -> Error<'gc> {
+        self.error
+    }
+
+    /// The active frames when the error occurred, from the innermost
+    /// outwards.
+    pub fn stack_trace(&self) -> &[StackFrame<'gc>] {
+        &self.stack_trace
+    }
+}
+
+impl<'gc> fmt::Display for CallError<'gc> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        for (index, stack_frame) in self.stack_trace.iter().enumerate() {
+            write!(f, "\n{:>4}: {}", index, stack_frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'gc> std::error::Error for CallError<'gc> {}
+
+#[derive(Clone, Debug)]
+pub struct StackFrame<'gc> {
+    mod_path: Vec<Ident<'gc>>,
+    ident: Ident<'gc>,
+    instr_index: usize,
+    line: Option<usize>,
+}
+
+impl<'gc> StackFrame<'gc> {
+    pub fn new(
+        mod_path: Vec<Ident<'gc>>,
+        ident: Ident<'gc>,
+        instr_index: usize,
+        line: Option<usize>,
+    ) -> StackFrame<'gc> {
+        StackFrame {
+            mod_path,
+            ident,
+            instr_index,
+            line,
+        }
+    }
+
+    pub fn mod_path(&self) -> &[Ident<'gc>] {
+        &self.mod_path
+    }
+
+    pub fn ident(&self) -> &Ident<'gc> {
+        &self.ident
+    }
+
+    pub fn instr_index(&self) -> usize {
+        self.instr_index
+    }
+
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+}
+
+impl<'gc> fmt::Display for StackFrame<'gc> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for ident in &self.mod_path {
+            write!(f, "{}::", ident)?;
+        }
+        write!(f, "{} at instruction {}", self.ident, self.instr_index)?;
+        if let Some(line) = self.line {
+            write!(f, ", line {}", line)?;
+        }
+        Ok(())
+    }
 }
@@ -1,38 +1,73 @@
 use eko_gc::{Arena, Gc, RefCell};
 
-use crate::core::fun::Chunk;
+use crate::core::fun::{Chunk, Fn};
 use crate::core::instr::Instr;
 use crate::core::value::Value;
 
-use super::error::{Error, Result};
+use super::error::{Error, Result, StackFrame};
 
 pub struct Frame<'gc> {
     cur_instr_index: usize,
+    stack_base: usize,
+    fun: Fn<'gc>,
     chunk: Chunk<'gc>,
     local_scope: Scope<'gc>,
     captured_scope: Option<CapturedScope<'gc>>,
 }
 
 impl<'gc> Frame<'gc> {
-    pub fn new(arena: &Arena<'gc>, chunk: Chunk<'gc>) -> Frame<'gc> {
+    /// Creates a frame running `chunk`, the body of `fun`, whose operands
+    /// start at `stack_base` on the operand stack.
+    pub fn new(
+        arena: &Arena<'gc>,
+        fun: Fn<'gc>,
+        chunk: Chunk<'gc>,
+        stack_base: usize,
+    ) -> Frame<'gc> {
+        let captured_scope_len = chunk.captured_scope_len();
+        let captured_scope = if captured_scope_len > 0 {
+            Some(CapturedScope::new(
+                arena,
+                Scope::new(arena, captured_scope_len),
+                captured_scope_len,
+                None,
+            ))
+        } else {
+            None
+        };
+
         Frame {
             cur_instr_index: 0,
+            stack_base,
+            fun,
             local_scope: Scope::new(arena, chunk.local_scope_len()),
             chunk,
-            captured_scope: None,
+            captured_scope,
         }
     }
 
+    /// Creates a frame for a closure, whose captured variables are reached
+    /// through `captured_scope`, one parent above the frame's own.
     pub fn with_captured_scope(
         arena: &Arena<'gc>,
+        fun: Fn<'gc>,
         chunk: Chunk<'gc>,
+        stack_base: usize,
         captured_scope: CapturedScope<'gc>,
     ) -> Frame<'gc> {
+        let captured_scope_len = chunk.captured_scope_len();
         Frame {
             cur_instr_index: 0,
+            stack_base,
+            fun,
             local_scope: Scope::new(arena, chunk.local_scope_len()),
             chunk,
-            captured_scope: Some(captured_scope),
+            captured_scope: Some(CapturedScope::new(
+                arena,
+                Scope::new(arena, captured_scope_len),
+                captured_scope_len,
+                Some(captured_scope),
+            )),
         }
     }
 
@@ -45,9 +80,76 @@ impl<'gc> Frame<'gc> {
         }
     }
 
+    /// Moves to the instruction at `index`, which may be one past the last
+    /// instruction to finish the frame.
+    pub fn jump(&mut self, index: usize) -> Result<'gc, ()> {
+        if index > self.chunk.len() {
+            return Err(Error::InvalidJump { index });
+        }
+        self.cur_instr_index = index;
+        Ok(())
+    }
+
+    pub fn cur_instr_index(&self) -> usize {
+        self.cur_instr_index
+    }
+
+    pub fn stack_base(&self) -> usize {
+        self.stack_base
+    }
+
+    pub fn fun(&self) -> &Fn<'gc> {
+        &self.fun
+    }
+
+    pub fn chunk(&self) -> &Chunk<'gc> {
+        &self.chunk
+    }
+
+    /// Describes where the frame is, which is the last instruction it ran.
+    pub fn stack_frame(&self) -> StackFrame<'gc> {
+        let instr_index = self.cur_instr_index.saturating_sub(1);
+        StackFrame::new(
+            self.fun.modu().path(),
+            self.fun.ident().clone(),
+            instr_index,
+            self.chunk.line(instr_index),
+        )
+    }
+
     pub fn local_scope(&self) -> &Scope<'gc> {
         &self.local_scope
     }
+
+    pub fn captured_scope(&self) -> Option<&CapturedScope<'gc>> {
+        self.captured_scope.as_ref()
+    }
+
+    /// Enters a captured scope of `len` variables, nested in the current
+    /// one. A frame without a captured scope is given an empty one first, so
+    /// that the current scope is always one parent above the new one.
+    pub fn push_captured_scope(&mut self, arena: &Arena<'gc>, len: usize) {
+        let parent_scope = self.captured_scope.take().unwrap_or_else(|| {
+            CapturedScope::new(arena, Scope::new(arena, 0), 0, None)
+        });
+        self.captured_scope = Some(CapturedScope::new(
+            arena,
+            Scope::new(arena, len),
+            len,
+            Some(parent_scope),
+        ));
+    }
+
+    /// Leaves the captured scope entered by `push_captured_scope`.
+    pub fn pop_captured_scope(&mut self) -> Result<'gc, ()> {
+        let parent_scope = self
+            .captured_scope
+            .as_ref()
+            .and_then(|scope| scope.0.parent_scope.clone())
+            .ok_or(Error::ParentNotFound)?;
+        self.captured_scope = Some(parent_scope);
+        Ok(())
+    }
 }
 
 // TODO: Avoid going through two layers of `Gc` and `RefCell`.
@@ -71,39 +173,36 @@ impl<'gc> CapturedScope<'gc> {
         ))
     }
 
-    // TODO: Make this iterative.
+    /// Sets `var` in the scope `parents` levels above this one.
     pub fn set(
         &self,
         parents: usize,
         var: usize,
         value: Value<'gc>,
     ) -> Result<'gc, ()> {
-        if parents == 0 {
-            if let Some(parent_scope) = &self.0.parent_scope {
-                return parent_scope.set(parents - 1, var, value);
-            } else {
-                return Err(Error::ParentNotFound);
-            }
-        }
-        if var >= self.0.captured_scope_len {
+        let scope = self.ancestor(parents)?;
+        if var >= scope.0.captured_scope_len {
             return Err(Error::VarNotFound { var });
         }
-        self.0.scope.set(var, value)
+        scope.0.scope.set(var, value)
     }
 
-    // TODO: Make this iterative.
+    /// Gets `var` from the scope `parents` levels above this one.
     pub fn get(&self, parents: usize, var: usize) -> Result<'gc, Value<'gc>> {
-        if parents == 0 {
-            if let Some(parent_scope) = &self.0.parent_scope {
-                return parent_scope.get(parents - 1, var);
-            } else {
-                return Err(Error::ParentNotFound);
-            }
-        }
-        if var >= self.0.captured_scope_len {
+        let scope = self.ancestor(parents)?;
+        if var >= scope.0.captured_scope_len {
             return Err(Error::VarNotFound { var });
         }
-        self.0.scope.get(var)
+        scope.0.scope.get(var)
+    }
+
+    fn ancestor(&self, parents: usize) -> Result<'gc, &CapturedScope<'gc>> {
+        let mut scope = self;
+        for _ in 0..parents {
+            scope =
+                scope.0.parent_scope.as_ref().ok_or(Error::ParentNotFound)?;
+        }
+        Ok(scope)
     }
 }
 
@@ -114,16 +213,14 @@ pub struct CapturedScopeData<'gc> {
     scope: Scope<'gc>,
 }
 
+/// The variables of a frame. Variables start out uninitialized, and can't be
+/// read until they are set.
 #[derive(Debug, Trace)]
-pub struct Scope<'gc>(Gc<'gc, RefCell<'gc, Vec<Value<'gc>>>>);
+pub struct Scope<'gc>(Gc<'gc, RefCell<'gc, Vec<Option<Value<'gc>>>>>);
 
 impl<'gc> Scope<'gc> {
     pub fn new(arena: &Arena<'gc>, len: usize) -> Scope<'gc> {
-        Scope(Gc::new(
-            arena,
-            // TODO: Figure out how to represent `None`.
-            RefCell::new(arena, vec![Value::Boolean(false); len]),
-        ))
+        Scope(Gc::new(arena, RefCell::new(arena, vec![None; len])))
     }
 
     pub fn set(&self, var: usize, value: Value<'gc>) -> Result<'gc, ()> {
@@ -131,15 +228,82 @@ impl<'gc> Scope<'gc> {
             .0
             .borrow_mut()
             .get_mut(var)
-            .ok_or_else(|| Error::VarNotFound { var })? = value;
+            .ok_or_else(|| Error::VarNotFound { var })? = Some(value);
         Ok(())
     }
 
     pub fn get(&self, var: usize) -> Result<'gc, Value<'gc>> {
-        self.0
-            .borrow()
-            .get(var)
-            .cloned()
-            .ok_or_else(|| Error::VarNotFound { var })
+        match self.0.borrow().get(var) {
+            Some(Some(value)) => Ok(value.clone()),
+            Some(None) => Err(Error::UninitializedVar { var }),
+            None => Err(Error::VarNotFound { var }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eko_gc::Arena;
+
+    use crate::core::value::Value;
+    use crate::engine::error::Error;
+
+    use super::{CapturedScope, Scope};
+
+    #[test]
+    fn captured_scope_parents() {
+        let arena = Arena::new();
+
+        let captured_scope = |len, parent_scope| {
+            CapturedScope::new(
+                &arena,
+                Scope::new(&arena, len),
+                len,
+                parent_scope,
+            )
+        };
+        let outer = captured_scope(1, None);
+        let middle = captured_scope(2, Some(outer.clone()));
+        let inner = captured_scope(1, Some(middle));
+
+        inner.set(0, 0, Value::Integer(1)).unwrap();
+        inner.set(1, 1, Value::Integer(2)).unwrap();
+        inner.set(2, 0, Value::Integer(3)).unwrap();
+
+        assert_eq!(inner.get(0, 0).unwrap(), Value::Integer(1));
+        assert_eq!(inner.get(1, 1).unwrap(), Value::Integer(2));
+        assert_eq!(inner.get(2, 0).unwrap(), Value::Integer(3));
+        assert_eq!(outer.get(0, 0).unwrap(), Value::Integer(3));
+
+        match inner.get(3, 0) {
+            Err(Error::ParentNotFound) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match inner.set(3, 0, Value::Integer(4)) {
+            Err(Error::ParentNotFound) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match inner.get(2, 1) {
+            Err(Error::VarNotFound { var: 1 }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn uninitialized_var() {
+        let arena = Arena::new();
+        let scope = Scope::new(&arena, 2);
+
+        scope.set(1, Value::Unit).unwrap();
+
+        match scope.get(0) {
+            Err(Error::UninitializedVar { var: 0 }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(scope.get(1).unwrap(), Value::Unit);
+        match scope.get(2) {
+            Err(Error::VarNotFound { var: 2 }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
     }
 }
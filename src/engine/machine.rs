@@ -1,18 +1,103 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::fmt;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
 
 use eko_gc::Arena;
 
 use crate::core::fun::{self, Chunk, Fn, FnProto};
-use crate::core::instr::Instr;
+use crate::core::ident::Ident;
+use crate::core::instr::{Fields, Instr};
 use crate::core::modu::Mod;
-use crate::core::value::Value;
+use crate::core::typ::{self, Kind};
+use crate::core::value::{
+    Closure, ClosureData, Enum, EnumData, List, ListData, Map, MapEntries,
+    String, Struct, StructData, Tuple, TupleData, Value, ValueKind,
+};
 
-use super::error::{Error, Result};
-use super::frame::Frame;
+use super::error::{CallError, Error, Result};
+use super::frame::{CapturedScope, CapturedScopeData, Frame, Scope};
+
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1024;
 
 pub struct Machine<'a, 'gc> {
     arena: &'a Arena<'gc>,
     operand_stack: OperandStack<'gc>,
+    frames: Vec<Frame<'gc>>,
+    suspended_calls: Vec<SuspendedCall>,
+    max_call_depth: usize,
+    fuel: Option<u64>,
+    interrupted: Arc<AtomicBool>,
+    heap: Heap,
+}
+
+/// Stops a running machine at the next instruction boundary, failing the
+/// call with `Error::Interrupted`. An interrupt that arrives while no call is
+/// running is dropped when the next outermost call starts, but still stops a
+/// suspended call when it is resumed.
+#[derive(Debug, Clone)]
+pub struct InterruptHandle {
+    interrupted: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, AtomicOrdering::Relaxed);
+    }
+}
+
+/// Accounts for the memory allocated on behalf of a machine.
+///
+/// Frame scopes are released when their frame is popped. Values can't be
+/// released one by one, as the arena doesn't report what it collects, so
+/// they count towards the limit until the outermost call is over.
+#[derive(Debug, Default)]
+struct Heap {
+    size: usize,
+    objects: usize,
+    max_size: Option<usize>,
+}
+
+impl Heap {
+    fn allocate<'gc>(&mut self, size: usize) -> Result<'gc, ()> {
+        self.grow(size)?;
+        self.objects += 1;
+        Ok(())
+    }
+
+    /// Accounts for an object that grew by `size` bytes.
+    fn grow<'gc>(&mut self, size: usize) -> Result<'gc, ()> {
+        let new_size = self.size.saturating_add(size);
+        if let Some(max_size) = self.max_size {
+            if new_size > max_size {
+                return Err(Error::OutOfMemory {
+                    max_heap_size: max_size,
+                });
+            }
+        }
+
+        self.size = new_size;
+        Ok(())
+    }
+
+    fn release(&mut self, size: usize) {
+        self.size -= size;
+        self.objects -= 1;
+    }
+
+    fn clear(&mut self) {
+        self.size = 0;
+        self.objects = 0;
+    }
+}
+
+/// A call that ran out of fuel, kept on the frame stack until it is resumed.
+struct SuspendedCall {
+    depth: usize,
+    stack_len: usize,
 }
 
 impl<'a, 'gc> Machine<'a, 'gc> {
@@ -20,16 +105,259 @@ impl<'a, 'gc> Machine<'a, 'gc> {
         Machine {
             arena,
             operand_stack: OperandStack::new(),
+            frames: Vec::new(),
+            suspended_calls: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            fuel: None,
+            interrupted: Arc::new(AtomicBool::new(false)),
+            heap: Heap::default(),
+        }
+    }
+
+    /// Sets the maximum number of nested chunk calls, beyond which calls
+    /// fail with `Error::CallDepthExceeded`.
+    pub fn max_call_depth(mut self, max_call_depth: usize) -> Machine<'a, 'gc> {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    /// Limits execution to `fuel` instructions, beyond which calls are
+    /// suspended with `Error::OutOfFuel`. Execution is unlimited by default.
+    pub fn fuel(mut self, fuel: u64) -> Machine<'a, 'gc> {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// The number of instructions left to run, if execution is limited.
+    pub fn remaining_fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Adds to the instruction budget so that a suspended call can be
+    /// resumed. Does nothing if execution is unlimited.
+    pub fn add_fuel(&mut self, fuel: u64) {
+        if let Some(remaining) = &mut self.fuel {
+            *remaining = remaining.saturating_add(fuel);
+        }
+    }
+
+    /// Whether a call ran out of fuel and is waiting to be resumed.
+    pub fn is_suspended(&self) -> bool {
+        !self.suspended_calls.is_empty()
+    }
+
+    /// Limits the memory allocated during each outermost call to
+    /// `max_heap_size` bytes, beyond which the call fails with
+    /// `Error::OutOfMemory`. Memory is unlimited by default.
+    pub fn max_heap_size(mut self, max_heap_size: usize) -> Machine<'a, 'gc> {
+        self.heap.max_size = Some(max_heap_size);
+        self
+    }
+
+    /// The number of bytes currently accounted to the machine.
+    pub fn heap_size(&self) -> usize {
+        self.heap.size
+    }
+
+    /// The number of objects currently accounted to the machine.
+    pub fn heap_objects(&self) -> usize {
+        self.heap.objects
+    }
+
+    /// Returns a handle that can stop the machine from another thread.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle {
+            interrupted: self.interrupted.clone(),
+        }
+    }
+
+    /// Calls the function below the `arity` arguments on the operand stack
+    /// and runs it to completion, leaving its return value in their place.
+    ///
+    /// If the call fails, the function, its arguments and everything the
+    /// call pushed are discarded, and the error carries the stack trace at
+    /// the point of failure. The exception is `Error::OutOfFuel`, which
+    /// leaves the call suspended so it can be continued with `resume`.
+    pub fn call(
+        &mut self,
+        arity: u8,
+        is_method: bool,
+    ) -> std::result::Result<(), CallError<'gc>> {
+        let call = SuspendedCall {
+            depth: self.frames.len(),
+            stack_len: self
+                .operand_stack
+                .len()
+                .saturating_sub(arity as usize + 1),
+        };
+
+        // Interrupts are meant for the call that was running when they were
+        // issued, not for this one.
+        if self.frames.is_empty() {
+            self.interrupted.store(false, AtomicOrdering::Relaxed);
+        }
+
+        let result = self.push_call(arity, is_method);
+        self.run_call(call, result)
+    }
+
+    /// Continues the most recently suspended call, as if `call` had been
+    /// made again.
+    pub fn resume(&mut self) -> std::result::Result<(), CallError<'gc>> {
+        match self.suspended_calls.pop() {
+            Some(call) => self.run_call(call, Ok(())),
+            None => Err(CallError::new(Error::NoSuspendedCall, Vec::new())),
+        }
+    }
+
+    fn run_call(
+        &mut self,
+        call: SuspendedCall,
+        result: Result<'gc, ()>,
+    ) -> std::result::Result<(), CallError<'gc>> {
+        let result = result.and_then(|()| self.run(call.depth));
+        let result = result.map_err(|error| {
+            let stack_trace =
+                self.frames.iter().rev().map(Frame::stack_frame).collect();
+
+            if let Error::OutOfFuel = error {
+                self.suspended_calls.push(call);
+            } else {
+                while self.frames.len() > call.depth {
+                    self.pop_frame();
+                }
+                self.reset_stack_base();
+                self.operand_stack
+                    .truncate(call.stack_len.max(self.operand_stack.base()));
+            }
+
+            CallError::new(error, stack_trace)
+        });
+
+        if self.frames.is_empty() {
+            self.heap.clear();
+        }
+        result
+    }
+
+    /// Runs the frames above `depth` until they have all returned.
+    fn run(&mut self, depth: usize) -> Result<'gc, ()> {
+        use self::Instr::*;
+
+        while self.frames.len() > depth {
+            if self.interrupted.load(AtomicOrdering::Relaxed) {
+                self.interrupted.store(false, AtomicOrdering::Relaxed);
+                return Err(Error::Interrupted);
+            }
+            if self.fuel == Some(0) {
+                return Err(Error::OutOfFuel);
+            }
+
+            let instr = match self.frames.last_mut().unwrap().step() {
+                Some(instr) => {
+                    if let Some(fuel) = &mut self.fuel {
+                        *fuel -= 1;
+                    }
+                    instr
+                }
+                None => {
+                    self.finish_frame()?;
+                    continue;
+                }
+            };
+
+            match instr {
+                PushValue { value } => self.load_value(value)?,
+                PushMod { modu } => self.push_mod(modu),
+                PushFn { fun } => self.push_fn(fun),
+                GetFn { ident } => self.get_fn(ident)?,
+                Pop => self.pop().map(|_| ())?,
+
+                PushVar { var } => self.push_var(var)?,
+                PopVar { var } => self.pop_var(var)?,
+                PushCapturedVar { parents, var } => {
+                    self.push_captured_var(parents, var)?
+                }
+                PopCapturedVar { parents, var } => {
+                    self.pop_captured_var(parents, var)?
+                }
+                PushCapturedScope { len } => self.push_captured_scope(len)?,
+                PopCapturedScope => self.pop_captured_scope()?,
+                MakeClosure => self.make_closure()?,
+
+                MakeTuple { len } => self.make_tuple(len)?,
+                MakeStruct { typ, fields } => self.make_struct(typ, fields)?,
+                MakeEnum {
+                    typ,
+                    variant,
+                    fields,
+                } => self.make_enum(typ, variant, fields)?,
+
+                MakeList { len } => self.make_list(len)?,
+                Index => self.index()?,
+                SetIndex => self.set_index()?,
+                Len => self.len()?,
+                ListPush => self.list_push()?,
+                ListPop => self.list_pop()?,
+                Slice => self.slice()?,
+                MakeMap { len } => self.make_map(len)?,
+                MapInsert => self.map_insert()?,
+                MapRemove => self.map_remove()?,
+                MapContains => self.map_contains()?,
+                MapKeys => self.map_keys()?,
+                CallMethod { ident, arity } => {
+                    self.call_method(ident, arity)?
+                }
+
+                GetField { field } => self.get_field(field)?,
+                SetField { field } => self.set_field(field)?,
+
+                Add => self.add()?,
+                Subtract => self.subtract()?,
+                Multiply => self.multiply()?,
+                Divide => self.divide()?,
+                FloorDivide => self.floor_divide()?,
+                Remainder => self.remainder()?,
+                Power => self.power()?,
+
+                BitAnd => self.bit_and()?,
+                BitOr => self.bit_or()?,
+                BitXor => self.bit_xor()?,
+                ShiftLeft => self.shift_left()?,
+                ShiftRight => self.shift_right()?,
+
+                Equal => self.equal()?,
+                NotEqual => self.not_equal()?,
+                Less => self.less()?,
+                LessEqual => self.less_equal()?,
+                Greater => self.greater()?,
+                GreaterEqual => self.greater_equal()?,
+                Not => self.not()?,
+                Negate => self.negate()?,
+
+                Jump { index } => self.jump(index)?,
+                JumpIfTrue { index } => self.jump_if_true(index)?,
+                JumpIfFalse { index } => self.jump_if_false(index)?,
+
+                Call { arity, is_method } => {
+                    self.push_call(arity, is_method)?
+                }
+                Return => self.ret()?,
+            }
         }
+
+        Ok(())
     }
 
-    pub fn call(&mut self, arity: u8, is_method: bool) -> Result<'gc, ()> {
+    /// Pops the function and its arguments, and either pushes a frame for
+    /// it or calls it right away if it is external.
+    fn push_call(&mut self, arity: u8, is_method: bool) -> Result<'gc, ()> {
         let mut args = Vec::new();
         for _ in 0..arity {
             args.push(self.operand_stack.pop_value()?);
         }
 
-        let fun = self.operand_stack.pop_fn()?;
+        let (fun, captured_scope) = self.operand_stack.pop_callee()?;
 
         if fun.arity() != arity {
             return Err(Error::WrongArity {
@@ -47,49 +375,92 @@ impl<'a, 'gc> Machine<'a, 'gc> {
         }
 
         match fun.proto() {
-            FnProto::Chunk(chunk) => self.call_chunk(chunk.clone(), args),
+            FnProto::Chunk(chunk) => self.push_frame(
+                fun.clone(),
+                chunk.clone(),
+                captured_scope,
+                args,
+            ),
             FnProto::External(external) => {
                 self.call_external(external.clone(), args)
             }
         }
     }
 
-    fn call_chunk(
+    fn push_frame(
         &mut self,
+        fun: Fn<'gc>,
         chunk: Chunk<'gc>,
-        mut args: Vec<Value<'gc>>,
+        captured_scope: Option<CapturedScope<'gc>>,
+        args: Vec<Value<'gc>>,
     ) -> Result<'gc, ()> {
-        use self::Instr::*;
-
-        let mut frame = Frame::new(self.arena, chunk);
-
-        for variable in 0..args.len() {
-            // TODO: Remove the `expect`.
-            frame.local_scope().set(variable, args.pop().unwrap())?;
+        if self.frames.len() >= self.max_call_depth {
+            return Err(Error::CallDepthExceeded {
+                max_call_depth: self.max_call_depth,
+            });
         }
 
-        while let Some(instr) = frame.step() {
-            match instr {
-                PushValue { value } => self.push_value(value),
-                PushMod { modu } => self.push_mod(modu),
-                PushFn { fun } => self.push_fn(fun),
-                Pop => self.pop().map(|_| ())?,
+        // The frame can't pop any of its caller's operands.
+        let stack_base = self.operand_stack.len();
+        let frame = match captured_scope {
+            Some(captured_scope) => Frame::with_captured_scope(
+                self.arena,
+                fun,
+                chunk,
+                stack_base,
+                captured_scope,
+            ),
+            None => Frame::new(self.arena, fun, chunk, stack_base),
+        };
 
-                PushVar { var } => self.push_var(&frame, var)?,
-                PopVar { var } => self.pop_var(&frame, var)?,
+        // The arguments were popped last to first.
+        for (variable, arg) in args.into_iter().rev().enumerate() {
+            frame.local_scope().set(variable, arg)?;
+        }
 
-                Add => self.add()?,
-                Subtract => self.subtract()?,
-                Multiply => self.multiply()?,
-                Divide => self.divide()?,
+        self.heap.allocate(frame_size(frame.chunk()))?;
+        self.frames.push(frame);
+        self.operand_stack.set_base(stack_base);
+        Ok(())
+    }
 
-                Call { arity, is_method } => self.call(arity, is_method)?,
-            }
+    /// Pops the current frame after it ran off the end of its chunk, which
+    /// returns the only operand it left.
+    fn finish_frame(&mut self) -> Result<'gc, ()> {
+        match self.operand_stack.len() - self.frame()?.stack_base() {
+            0 => return Err(Error::EmptyOperandStack),
+            1 => {}
+            len => return Err(Error::LeftoverOperands { len }),
         }
 
+        self.pop_frame();
+        self.reset_stack_base();
         Ok(())
     }
 
+    fn pop_frame(&mut self) -> Option<Frame<'gc>> {
+        let frame = self.frames.pop()?;
+        self.heap.release(frame_size(frame.chunk()));
+        Some(frame)
+    }
+
+    fn reset_stack_base(&mut self) {
+        let stack_base = self.frames.last().map_or(0, Frame::stack_base);
+        self.operand_stack.set_base(stack_base);
+    }
+
+    fn captured_scope(&self) -> Result<'gc, &CapturedScope<'gc>> {
+        self.frame()?.captured_scope().ok_or(Error::ParentNotFound)
+    }
+
+    fn frame(&self) -> Result<'gc, &Frame<'gc>> {
+        self.frames.last().ok_or(Error::EmptyCallStack)
+    }
+
+    fn frame_mut(&mut self) -> Result<'gc, &mut Frame<'gc>> {
+        self.frames.last_mut().ok_or(Error::EmptyCallStack)
+    }
+
     fn call_external(
         &mut self,
         external: fun::External<'gc>,
@@ -103,6 +474,16 @@ impl<'a, 'gc> Machine<'a, 'gc> {
         self.operand_stack.push_value(value);
     }
 
+    /// Pushes a constant from a chunk. A string constant counts as allocated
+    /// each time it is loaded, as earlier loads may still be live.
+    fn load_value(&mut self, value: Value<'gc>) -> Result<'gc, ()> {
+        if let Value::String(string) = &value {
+            self.heap.allocate(string_size(string.byte_len()))?;
+        }
+        self.operand_stack.push_value(value);
+        Ok(())
+    }
+
     pub fn push_mod(&mut self, modu: Mod<'gc>) {
         self.operand_stack.push_mod(modu);
     }
@@ -111,200 +492,1165 @@ impl<'a, 'gc> Machine<'a, 'gc> {
         self.operand_stack.push_fn(fun);
     }
 
-    pub fn pop(&mut self) -> Result<'gc, ()> {
-        self.operand_stack.pop_value().map(|_| ())
+    pub fn get_fn(&mut self, ident: Ident<'gc>) -> Result<'gc, ()> {
+        let modu = self.operand_stack.pop_mod()?;
+        let fun = modu.fun(&ident).ok_or(Error::FnNotFound { ident })?;
+        Ok(self.operand_stack.push_fn(fun))
+    }
+
+    pub fn pop(&mut self) -> Result<'gc, Value<'gc>> {
+        self.operand_stack.pop_value()
     }
 
-    pub fn push_var(
+    pub fn push_var(&mut self, var: usize) -> Result<'gc, ()> {
+        let value = self.frame()?.local_scope().get(var)?;
+        Ok(self.operand_stack.push_value(value))
+    }
+
+    pub fn pop_var(&mut self, var: usize) -> Result<'gc, ()> {
+        let value = self.operand_stack.pop_value()?;
+        self.frame()?.local_scope().set(var, value)
+    }
+
+    pub fn push_captured_var(
         &mut self,
-        frame: &Frame<'gc>,
+        parents: usize,
         var: usize,
     ) -> Result<'gc, ()> {
-        let value = frame.local_scope().get(var)?.clone();
-        Ok(self.operand_stack.push_value(value))
+        let value = self.captured_scope()?.get(parents, var)?;
+        self.operand_stack.push_value(value);
+        Ok(())
     }
 
-    pub fn pop_var(
+    pub fn pop_captured_var(
         &mut self,
-        frame: &Frame<'gc>,
+        parents: usize,
         var: usize,
     ) -> Result<'gc, ()> {
         let value = self.operand_stack.pop_value()?;
-        frame.local_scope().set(var, value)
+        self.captured_scope()?.set(parents, var, value)
     }
 
-    pub fn add(&mut self) -> Result<'gc, ()> {
-        let right_value = self.operand_stack.pop_value()?;
-        let left_value = self.operand_stack.pop_value()?;
+    /// Enters a fresh captured scope of `len` variables in the current frame,
+    /// such as for an iteration of a loop whose body declares captured
+    /// variables.
+    pub fn push_captured_scope(&mut self, len: usize) -> Result<'gc, ()> {
+        // Closures can keep the scope alive after it is left, so it is
+        // accounted like a value rather than with the frame.
+        self.heap.allocate(captured_scope_size(len))?;
+        let arena = self.arena;
+        self.frame_mut()?.push_captured_scope(arena, len);
+        Ok(())
+    }
 
-        let value = match (left_value, right_value) {
-            (Value::Integer(left), Value::Integer(right)) => {
-                Value::Integer(left + right)
-            }
-            (Value::Integer(left), Value::Float(right)) => {
-                Value::Float(left as f64 + right)
-            }
-            (Value::Float(left), Value::Integer(right)) => {
-                Value::Float(left + right as f64)
-            }
-            (Value::Float(left), Value::Float(right)) => {
-                Value::Float(left + right)
-            }
-            _ => unimplemented!(),
+    pub fn pop_captured_scope(&mut self) -> Result<'gc, ()> {
+        self.frame_mut()?.pop_captured_scope()
+    }
+
+    /// Pops a function and pushes a closure of it over the current frame's
+    /// captured variables.
+    pub fn make_closure(&mut self) -> Result<'gc, ()> {
+        let fun = self.operand_stack.pop_fn()?;
+        let frame = self.frame()?;
+        let captured_scope_len = frame.chunk().captured_scope_len();
+        let captured_scope = match frame.captured_scope() {
+            Some(captured_scope) => captured_scope.clone(),
+            // Nothing outside the closure is captured, so it never reaches
+            // past its own scope.
+            None => CapturedScope::new(
+                self.arena,
+                Scope::new(self.arena, 0),
+                0,
+                None,
+            ),
         };
 
-        Ok(self.operand_stack.push_value(value))
+        // The closure keeps the captured scope alive after its frame is
+        // popped, so it is accounted to the closure as well.
+        self.heap.allocate(
+            mem::size_of::<ClosureData>()
+                + captured_scope_size(captured_scope_len),
+        )?;
+        let closure = Closure::new(self.arena, fun, captured_scope);
+        self.operand_stack.push_value(Value::Closure(closure));
+        Ok(())
     }
 
-    pub fn subtract(&mut self) -> Result<'gc, ()> {
-        let right_value = self.operand_stack.pop_value()?;
-        let left_value = self.operand_stack.pop_value()?;
+    /// Pops `len` values and pushes a tuple of them.
+    pub fn make_tuple(&mut self, len: u8) -> Result<'gc, ()> {
+        let fields = self.operand_stack.pop_values(len as usize)?;
 
-        let value = match (left_value, right_value) {
-            (Value::Integer(left), Value::Integer(right)) => {
-                Value::Integer(left - right)
+        self.heap.allocate(
+            mem::size_of::<TupleData>() + fields_size(fields.len()),
+        )?;
+        let tuple = Tuple::new(self.arena, fields);
+        self.operand_stack.push_value(Value::Tuple(tuple));
+        Ok(())
+    }
+
+    /// Pops the `fields` and pushes a struct of type `typ` with them.
+    pub fn make_struct(
+        &mut self,
+        typ: typ::Struct<'gc>,
+        fields: Fields<'gc>,
+    ) -> Result<'gc, ()> {
+        let strukt = match fields {
+            Fields::Tuple(len) => {
+                let fields = self.operand_stack.pop_values(len as usize)?;
+                self.heap.allocate(
+                    mem::size_of::<StructData>() + fields_size(fields.len()),
+                )?;
+                Struct::new_tuple(self.arena, typ, fields)?
             }
-            (Value::Integer(left), Value::Float(right)) => {
-                Value::Float(left as f64 - right)
+            Fields::Map(idents) => {
+                let fields = self.pop_map_fields(idents)?;
+                self.heap.allocate(
+                    mem::size_of::<StructData>() + fields_size(fields.len()),
+                )?;
+                Struct::new_map(self.arena, typ, fields)?
             }
-            (Value::Float(left), Value::Integer(right)) => {
-                Value::Float(left - right as f64)
+        };
+
+        self.operand_stack.push_value(Value::Struct(strukt));
+        Ok(())
+    }
+
+    /// Pops the `fields` and pushes the `variant` of the enum type `typ`
+    /// with them.
+    pub fn make_enum(
+        &mut self,
+        typ: typ::Enum<'gc>,
+        variant: u8,
+        fields: Fields<'gc>,
+    ) -> Result<'gc, ()> {
+        let enu = match fields {
+            Fields::Tuple(len) => {
+                let fields = self.operand_stack.pop_values(len as usize)?;
+                self.heap.allocate(
+                    mem::size_of::<EnumData>() + fields_size(fields.len()),
+                )?;
+                Enum::new_tuple(self.arena, typ, variant, fields)?
             }
-            (Value::Float(left), Value::Float(right)) => {
-                Value::Float(left - right)
+            Fields::Map(idents) => {
+                let fields = self.pop_map_fields(idents)?;
+                self.heap.allocate(
+                    mem::size_of::<EnumData>() + fields_size(fields.len()),
+                )?;
+                Enum::new_map(self.arena, typ, variant, fields)?
             }
-            _ => unimplemented!(),
         };
 
-        Ok(self.operand_stack.push_value(value))
+        self.operand_stack.push_value(Value::Enum(enu));
+        Ok(())
     }
 
-    pub fn multiply(&mut self) -> Result<'gc, ()> {
-        let right_value = self.operand_stack.pop_value()?;
-        let left_value = self.operand_stack.pop_value()?;
+    fn pop_map_fields(
+        &mut self,
+        idents: Vec<Ident<'gc>>,
+    ) -> Result<'gc, BTreeMap<Ident<'gc>, Value<'gc>>> {
+        let values = self.operand_stack.pop_values(idents.len())?;
+        Ok(idents.into_iter().zip(values).collect())
+    }
 
-        let value = match (left_value, right_value) {
-            (Value::Integer(left), Value::Integer(right)) => {
-                Value::Integer(left * right)
-            }
-            (Value::Integer(left), Value::Float(right)) => {
-                Value::Float(left as f64 - right)
+    /// Pops `len` values and pushes a list of them.
+    pub fn make_list(&mut self, len: usize) -> Result<'gc, ()> {
+        let values = self.operand_stack.pop_values(len)?;
+
+        self.heap
+            .allocate(mem::size_of::<ListData>() + fields_size(values.len()))?;
+        let list = List::new(self.arena, values);
+        self.operand_stack.push_value(Value::List(list));
+        Ok(())
+    }
+
+    /// Pops an index and a list, map or string, and pushes the value at the
+    /// index. Strings are indexed by char.
+    pub fn index(&mut self) -> Result<'gc, ()> {
+        let index = self.operand_stack.pop_value()?;
+        let value = match self.operand_stack.pop_value()? {
+            Value::List(list) => {
+                let index = integer(index)?;
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|i| list.get(i))
+                    .ok_or_else(|| Error::IndexOutOfBounds {
+                        index,
+                        len: list.len(),
+                    })?
             }
-            (Value::Float(left), Value::Integer(right)) => {
-                Value::Float(left * right as f64)
+            Value::Map(map) => map.get(&index)?.ok_or(Error::KeyNotFound)?,
+            Value::String(string) => {
+                let index = integer(index)?;
+                let ch = usize::try_from(index)
+                    .ok()
+                    .and_then(|i| string.char_at(self.arena, i))
+                    .ok_or_else(|| Error::IndexOutOfBounds {
+                        index,
+                        len: string.char_len(),
+                    })?;
+                self.heap.allocate(string_size(ch.byte_len()))?;
+                Value::String(ch)
             }
-            (Value::Float(left), Value::Float(right)) => {
-                Value::Float(left * right)
+            value => {
+                return Err(Error::UnsupportedOperand {
+                    op: "[]",
+                    operand: ValueKind::from(&value),
+                })
             }
-            _ => unimplemented!(),
         };
 
-        Ok(self.operand_stack.push_value(value))
+        self.operand_stack.push_value(value);
+        Ok(())
     }
 
-    pub fn divide(&mut self) -> Result<'gc, ()> {
-        let right_value = self.operand_stack.pop_value()?;
-        let left_value = self.operand_stack.pop_value()?;
-
-        let value = match (left_value, right_value) {
-            (Value::Integer(left), Value::Integer(right)) => {
-                Value::Integer(left / right)
-            }
-            (Value::Integer(left), Value::Float(right)) => {
-                Value::Float(left as f64 / right)
-            }
-            (Value::Float(left), Value::Integer(right)) => {
-                Value::Float(left / right as f64)
+    /// Pops a value, an index and a list or map, and sets the value at the
+    /// index. Maps gain the index as a key if they don't already have it.
+    pub fn set_index(&mut self) -> Result<'gc, ()> {
+        let value = self.operand_stack.pop_value()?;
+        let index = self.operand_stack.pop_value()?;
+        match self.operand_stack.pop_value()? {
+            Value::List(list) => {
+                let index = integer(index)?;
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|i| list.set(i, value))
+                    .ok_or_else(|| Error::IndexOutOfBounds {
+                        index,
+                        len: list.len(),
+                    })
             }
-            (Value::Float(left), Value::Float(right)) => {
-                Value::Float(left / right)
+            Value::Map(map) => self.insert(map, index, value),
+            value => Err(Error::UnsupportedOperand {
+                op: "[]",
+                operand: ValueKind::from(&value),
+            }),
+        }
+    }
+
+    /// Pops a list, map or string and pushes its length. The length of a
+    /// string is in chars.
+    pub fn len(&mut self) -> Result<'gc, ()> {
+        let len = match self.operand_stack.pop_value()? {
+            Value::List(list) => list.len(),
+            Value::Map(map) => map.len(),
+            Value::String(string) => string.char_len(),
+            value => {
+                return Err(Error::UnsupportedOperand {
+                    op: "len",
+                    operand: ValueKind::from(&value),
+                })
             }
-            _ => unimplemented!(),
         };
 
-        Ok(self.operand_stack.push_value(value))
+        let len = Value::Integer(len as i64);
+        self.operand_stack.push_value(len);
+        Ok(())
     }
-}
 
-pub struct OperandStack<'gc>(Vec<Operand<'gc>>);
+    /// Pops a value and a list, and appends the value to the list.
+    pub fn list_push(&mut self) -> Result<'gc, ()> {
+        let value = self.operand_stack.pop_value()?;
+        let list = self.pop_list("push")?;
 
-impl<'gc> OperandStack<'gc> {
-    pub fn new() -> OperandStack<'gc> {
-        OperandStack(Vec::new())
+        self.heap.grow(mem::size_of::<Value>())?;
+        list.push(value);
+        Ok(())
     }
 
-    pub fn push_mod(&mut self, modu: Mod<'gc>) {
-        self.0.push(Operand::Mod(modu))
+    /// Pops a list, and removes and pushes its last value.
+    pub fn list_pop(&mut self) -> Result<'gc, ()> {
+        let list = self.pop_list("pop")?;
+        let value = list.pop().ok_or(Error::EmptyList)?;
+        self.operand_stack.push_value(value);
+        Ok(())
     }
 
-    pub fn pop_mod(&mut self) -> Result<'gc, Mod<'gc>> {
-        use self::Operand::*;
+    /// Pops an end, a start and a list or string, and pushes a new list or
+    /// string of what lies from the start up to the end. Strings are sliced
+    /// by char.
+    pub fn slice(&mut self) -> Result<'gc, ()> {
+        let end = self.operand_stack.pop_integer()?;
+        let start = self.operand_stack.pop_integer()?;
+        let value = self.operand_stack.pop_value()?;
 
-        match self.0.pop() {
-            Some(Mod(modu)) => Ok(modu),
-            Some(operand) => Err(Error::InvalidOperandKind {
-                expected: OperandKind::Mod,
-                received: operand.into(),
-            }),
-            None => Err(Error::EmptyOperandStack),
-        }
-    }
+        let len = match &value {
+            Value::List(list) => list.len(),
+            Value::String(string) => string.char_len(),
+            value => {
+                return Err(Error::UnsupportedOperand {
+                    op: "slice",
+                    operand: ValueKind::from(value),
+                })
+            }
+        };
+        let range = match (usize::try_from(start), usize::try_from(end)) {
+            (Ok(start), Ok(end)) if start <= end && end <= len => start..end,
+            _ => return Err(Error::InvalidSlice { start, end, len }),
+        };
 
-    pub fn push_fn(&mut self, fun: Fn<'gc>) {
-        self.0.push(Operand::Fn(fun))
+        let slice = match value {
+            Value::List(list) => {
+                self.heap.allocate(
+                    mem::size_of::<ListData>() + fields_size(range.len()),
+                )?;
+                list.slice(self.arena, range.start, range.end)
+                    .map(Value::List)
+            }
+            Value::String(string) => {
+                let slice = string.slice(self.arena, range.start, range.end);
+                if let Some(slice) = &slice {
+                    self.heap.allocate(string_size(slice.byte_len()))?;
+                }
+                slice.map(Value::String)
+            }
+            _ => unreachable!(),
+        };
+
+        let slice = slice.ok_or(Error::InvalidSlice { start, end, len })?;
+        self.operand_stack.push_value(slice);
+        Ok(())
     }
 
-    pub fn pop_fn(&mut self) -> Result<'gc, Fn<'gc>> {
-        use self::Operand::*;
+    /// Pops `len` pairs of keys and values and pushes a map of them, in the
+    /// order they were pushed.
+    pub fn make_map(&mut self, len: usize) -> Result<'gc, ()> {
+        let values = self.operand_stack.pop_values(len * 2)?;
 
-        match self.0.pop() {
-            Some(Fn(fun)) => Ok(fun),
-            Some(operand) => Err(Error::InvalidOperandKind {
-                expected: OperandKind::Fn,
-                received: operand.into(),
-            }),
-            None => Err(Error::EmptyOperandStack),
+        self.heap.allocate(mem::size_of::<MapEntries>())?;
+        let map = Map::new(self.arena);
+        let mut values = values.into_iter();
+        while let (Some(key), Some(value)) = (values.next(), values.next()) {
+            self.insert(map.clone(), key, value)?;
         }
+
+        self.operand_stack.push_value(Value::Map(map));
+        Ok(())
     }
 
-    pub fn push_value(&mut self, value: Value<'gc>) {
-        self.0.push(Operand::Value(value))
+    /// Pops a value, a key and a map, and inserts the value under the key.
+    pub fn map_insert(&mut self) -> Result<'gc, ()> {
+        let value = self.operand_stack.pop_value()?;
+        let key = self.operand_stack.pop_value()?;
+        let map = self.pop_map("insert")?;
+        self.insert(map, key, value)
     }
 
-    pub fn pop_value(&mut self) -> Result<'gc, Value<'gc>> {
-        use self::Operand::*;
+    /// Pops a key and a map, and removes and pushes the value under the key.
+    pub fn map_remove(&mut self) -> Result<'gc, ()> {
+        let key = self.operand_stack.pop_value()?;
+        let map = self.pop_map("remove")?;
+        let value = map.remove(&key)?.ok_or(Error::KeyNotFound)?;
+        self.operand_stack.push_value(value);
+        Ok(())
+    }
 
-        match self.0.pop() {
-            Some(Value(value)) => Ok(value),
-            Some(operand) => Err(Error::InvalidOperandKind {
-                expected: OperandKind::Value,
-                received: operand.into(),
-            }),
-            None => Err(Error::EmptyOperandStack),
-        }
+    /// Pops a key and a map, and pushes whether the map has the key.
+    pub fn map_contains(&mut self) -> Result<'gc, ()> {
+        let key = self.operand_stack.pop_value()?;
+        let map = self.pop_map("contains")?;
+        let contains = Value::Boolean(map.contains(&key)?);
+        self.operand_stack.push_value(contains);
+        Ok(())
     }
-}
 
-pub enum Operand<'gc> {
-    Mod(Mod<'gc>),
-    Fn(Fn<'gc>),
-    Value(Value<'gc>),
-}
+    /// Pops a map and pushes a list of its keys in insertion order.
+    pub fn map_keys(&mut self) -> Result<'gc, ()> {
+        let map = self.pop_map("keys")?;
+        let keys = map.keys();
 
-#[derive(Debug)]
-pub enum OperandKind {
-    Mod,
-    Fn,
-    Value,
-}
+        self.heap
+            .allocate(mem::size_of::<ListData>() + fields_size(keys.len()))?;
+        let keys = List::new(self.arena, keys);
+        self.operand_stack.push_value(Value::List(keys));
+        Ok(())
+    }
 
-impl<'gc> From<Operand<'gc>> for OperandKind {
-    fn from(operand: Operand<'gc>) -> OperandKind {
-        use self::OperandKind::*;
+    fn insert(
+        &mut self,
+        map: Map<'gc>,
+        key: Value<'gc>,
+        value: Value<'gc>,
+    ) -> Result<'gc, ()> {
+        if !map.contains(&key)? {
+            self.heap.grow(fields_size(2))?;
+        }
+        map.insert(key, value)?;
+        Ok(())
+    }
+
+    /// Pops the `arity` arguments and the receiver below them, and calls the
+    /// built-in method `ident` of the receiver.
+    pub fn call_method(
+        &mut self,
+        ident: Ident<'gc>,
+        arity: u8,
+    ) -> Result<'gc, ()> {
+        let args = self.operand_stack.pop_values(arity as usize)?;
+        let receiver = self.operand_stack.pop_value()?;
+
+        let name = match &ident {
+            Ident::String(name) => name.as_str(),
+            Ident::Number(_) => return Err(Error::MethodNotFound { ident }),
+        };
+        let (expected, method): (u8, fn(&mut Self) -> Result<'gc, ()>) =
+            match (&receiver, name) {
+                (Value::Integer(_), "div_floor") => (1, Machine::floor_divide),
+                (Value::Float(_), "div_floor") => (1, Machine::floor_divide),
+                (Value::List(_), "len") => (0, Machine::len),
+                (Value::List(_), "push") => (1, Machine::list_push),
+                (Value::List(_), "pop") => (0, Machine::list_pop),
+                (Value::List(_), "slice") => (2, Machine::slice),
+                (Value::Map(_), "len") => (0, Machine::len),
+                (Value::Map(_), "insert") => (2, Machine::map_insert),
+                (Value::Map(_), "remove") => (1, Machine::map_remove),
+                (Value::Map(_), "contains") => (1, Machine::map_contains),
+                (Value::Map(_), "keys") => (0, Machine::map_keys),
+                (Value::String(_), "len") => (0, Machine::len),
+                (Value::String(_), "byte_len") => (0, Machine::string_byte_len),
+                (Value::String(_), "slice") => (2, Machine::slice),
+                (Value::String(_), "find") => (1, Machine::string_find),
+                (Value::String(_), "contains") => (1, Machine::string_contains),
+                (Value::String(_), "replace") => (2, Machine::string_replace),
+                (Value::String(_), "split") => (1, Machine::string_split),
+                (Value::String(_), "to_uppercase") => {
+                    (0, Machine::string_to_uppercase)
+                }
+                (Value::String(_), "to_lowercase") => {
+                    (0, Machine::string_to_lowercase)
+                }
+                _ => return Err(Error::MethodNotFound { ident }),
+            };
+        if arity != expected {
+            return Err(Error::WrongArity {
+                expected,
+                received: arity,
+            });
+        }
+
+        let len = self.operand_stack.len();
+        self.operand_stack.push_value(receiver);
+        for arg in args {
+            self.operand_stack.push_value(arg);
+        }
+        method(self)?;
+
+        // Methods that only mutate the receiver still leave a result.
+        if self.operand_stack.len() == len {
+            self.operand_stack.push_value(Value::Unit);
+        }
+        Ok(())
+    }
+
+    fn string_byte_len(&mut self) -> Result<'gc, ()> {
+        let string = self.operand_stack.pop_string()?;
+        let len = Value::Integer(string.byte_len() as i64);
+        self.operand_stack.push_value(len);
+        Ok(())
+    }
+
+    /// Pushes the char index of the first occurrence of a pattern in a
+    /// string, or unit if there is none.
+    fn string_find(&mut self) -> Result<'gc, ()> {
+        let pattern = self.operand_stack.pop_string()?;
+        let string = self.operand_stack.pop_string()?;
+
+        let index = string.find(&pattern.string());
+        let index = index.map_or(Value::Unit, |i| Value::Integer(i as i64));
+        self.operand_stack.push_value(index);
+        Ok(())
+    }
+
+    fn string_contains(&mut self) -> Result<'gc, ()> {
+        let pattern = self.operand_stack.pop_string()?;
+        let string = self.operand_stack.pop_string()?;
+
+        let contains = Value::Boolean(string.contains(&pattern.string()));
+        self.operand_stack.push_value(contains);
+        Ok(())
+    }
+
+    fn string_replace(&mut self) -> Result<'gc, ()> {
+        let to = self.operand_stack.pop_string()?;
+        let from = self.operand_stack.pop_string()?;
+        let string = self.operand_stack.pop_string()?;
+
+        let string = string.replace(self.arena, &from.string(), &to.string());
+        self.heap.allocate(string_size(string.byte_len()))?;
+        self.operand_stack.push_value(Value::String(string));
+        Ok(())
+    }
+
+    /// Pushes a list of the parts of a string between a separator.
+    fn string_split(&mut self) -> Result<'gc, ()> {
+        let separator = self.operand_stack.pop_string()?;
+        let string = self.operand_stack.pop_string()?;
+        if separator.byte_len() == 0 {
+            return Err(Error::EmptySeparator);
+        }
+
+        let parts = string.split(self.arena, &separator.string());
+        for part in &parts {
+            self.heap.allocate(string_size(part.byte_len()))?;
+        }
+        self.heap
+            .allocate(mem::size_of::<ListData>() + fields_size(parts.len()))?;
+
+        let parts = parts.into_iter().map(Value::String).collect();
+        let list = List::new(self.arena, parts);
+        self.operand_stack.push_value(Value::List(list));
+        Ok(())
+    }
+
+    fn string_to_uppercase(&mut self) -> Result<'gc, ()> {
+        let string = self.operand_stack.pop_string()?;
+        let string = string.to_uppercase(self.arena);
+        self.heap.allocate(string_size(string.byte_len()))?;
+        self.operand_stack.push_value(Value::String(string));
+        Ok(())
+    }
+
+    fn string_to_lowercase(&mut self) -> Result<'gc, ()> {
+        let string = self.operand_stack.pop_string()?;
+        let string = string.to_lowercase(self.arena);
+        self.heap.allocate(string_size(string.byte_len()))?;
+        self.operand_stack.push_value(Value::String(string));
+        Ok(())
+    }
+
+    fn pop_map(&mut self, op: &'static str) -> Result<'gc, Map<'gc>> {
+        match self.operand_stack.pop_value()? {
+            Value::Map(map) => Ok(map),
+            value => Err(Error::UnsupportedOperand {
+                op,
+                operand: ValueKind::from(&value),
+            }),
+        }
+    }
+
+    fn pop_list(&mut self, op: &'static str) -> Result<'gc, List<'gc>> {
+        match self.operand_stack.pop_value()? {
+            Value::List(list) => Ok(list),
+            value => Err(Error::UnsupportedOperand {
+                op,
+                operand: ValueKind::from(&value),
+            }),
+        }
+    }
+
+    /// Pops a value and pushes its `field`. Tuple fields are numbered, and
+    /// map fields are named.
+    pub fn get_field(&mut self, field: Ident<'gc>) -> Result<'gc, ()> {
+        let value = self.operand_stack.pop_value()?;
+
+        let field_value = match (&value, field) {
+            (Value::Tuple(tuple), Ident::Number(index)) => {
+                tuple.field(index)?
+            }
+            (Value::Struct(strukt), Ident::Number(index)) => {
+                strukt.tuple_field(index)?
+            }
+            (Value::Struct(strukt), field) => strukt.map_field(field)?,
+            (Value::Enum(enu), Ident::Number(index)) => {
+                enu.tuple_field(index)?
+            }
+            (Value::Enum(enu), field) => enu.map_field(field)?,
+            (value, _) => return Err(field_error(value)),
+        };
+
+        self.operand_stack.push_value(field_value);
+        Ok(())
+    }
+
+    /// Pops a value and the value below it, and sets `field` of the latter
+    /// to the former.
+    pub fn set_field(&mut self, field: Ident<'gc>) -> Result<'gc, ()> {
+        let field_value = self.operand_stack.pop_value()?;
+        let value = self.operand_stack.pop_value()?;
+
+        match (&value, field) {
+            (Value::Tuple(tuple), Ident::Number(index)) => {
+                tuple.set_field(index, field_value)?
+            }
+            (Value::Struct(strukt), Ident::Number(index)) => {
+                strukt.set_tuple_field(index, field_value)?
+            }
+            (Value::Struct(strukt), field) => {
+                strukt.set_map_field(field, field_value)?
+            }
+            (Value::Enum(enu), Ident::Number(index)) => {
+                enu.set_tuple_field(index, field_value)?
+            }
+            (Value::Enum(enu), field) => {
+                enu.set_map_field(field, field_value)?
+            }
+            (value, _) => return Err(field_error(value)),
+        }
+
+        Ok(())
+    }
+
+    /// Returns the top operand from the current frame, discarding any
+    /// others it left, so that `return` can be used in the middle of an
+    /// expression.
+    pub fn ret(&mut self) -> Result<'gc, ()> {
+        let value = self.operand_stack.pop_value()?;
+        let frame = self.pop_frame().ok_or(Error::EmptyCallStack)?;
+        self.operand_stack.truncate(frame.stack_base());
+        self.operand_stack.push_value(value);
+        self.reset_stack_base();
+        Ok(())
+    }
+
+    pub fn jump(&mut self, index: usize) -> Result<'gc, ()> {
+        self.frame_mut()?.jump(index)
+    }
+
+    pub fn jump_if_true(&mut self, index: usize) -> Result<'gc, ()> {
+        if self.operand_stack.pop_boolean()? {
+            self.frame_mut()?.jump(index)?;
+        }
+        Ok(())
+    }
+
+    pub fn jump_if_false(&mut self, index: usize) -> Result<'gc, ()> {
+        if !self.operand_stack.pop_boolean()? {
+            self.frame_mut()?.jump(index)?;
+        }
+        Ok(())
+    }
+
+    /// Adds two numbers, or concatenates two strings.
+    pub fn add(&mut self) -> Result<'gc, ()> {
+        let right_value = self.operand_stack.pop_value()?;
+        let left_value = self.operand_stack.pop_value()?;
+
+        let value = match (&left_value, &right_value) {
+            (Value::String(left), Value::String(right)) => {
+                self.heap.allocate(string_size(
+                    left.byte_len() + right.byte_len(),
+                ))?;
+                Value::String(left.concat(self.arena, right))
+            }
+            (left, right) => arithmetic_value(
+                "+",
+                left,
+                right,
+                |left, right| {
+                    left.checked_add(right)
+                        .ok_or(Error::IntegerOverflow { op: "+" })
+                },
+                |left, right| left + right,
+            )?,
+        };
+
+        Ok(self.operand_stack.push_value(value))
+    }
+
+    pub fn subtract(&mut self) -> Result<'gc, ()> {
+        self.arithmetic(
+            "-",
+            |left, right| {
+                left.checked_sub(right)
+                    .ok_or(Error::IntegerOverflow { op: "-" })
+            },
+            |left, right| left - right,
+        )
+    }
+
+    pub fn multiply(&mut self) -> Result<'gc, ()> {
+        self.arithmetic(
+            "*",
+            |left, right| {
+                left.checked_mul(right)
+                    .ok_or(Error::IntegerOverflow { op: "*" })
+            },
+            |left, right| left * right,
+        )
+    }
+
+    pub fn divide(&mut self) -> Result<'gc, ()> {
+        self.arithmetic(
+            "/",
+            |left, right| {
+                if right == 0 {
+                    return Err(Error::DivisionByZero);
+                }
+                left.checked_div(right)
+                    .ok_or(Error::IntegerOverflow { op: "/" })
+            },
+            |left, right| left / right,
+        )
+    }
+
+    /// Divides and rounds towards negative infinity. Scripts reach this
+    /// through the `div_floor` method of numbers.
+    pub fn floor_divide(&mut self) -> Result<'gc, ()> {
+        self.arithmetic(
+            "div_floor",
+            |left, right| {
+                if right == 0 {
+                    return Err(Error::DivisionByZero);
+                }
+                let quotient = left
+                    .checked_div(right)
+                    .ok_or(Error::IntegerOverflow { op: "div_floor" })?;
+                if left % right != 0 && (left < 0) != (right < 0) {
+                    Ok(quotient - 1)
+                } else {
+                    Ok(quotient)
+                }
+            },
+            |left, right| (left / right).floor(),
+        )
+    }
+
+    pub fn remainder(&mut self) -> Result<'gc, ()> {
+        self.arithmetic(
+            "%",
+            |left, right| {
+                if right == 0 {
+                    return Err(Error::DivisionByZero);
+                }
+                left.checked_rem(right)
+                    .ok_or(Error::IntegerOverflow { op: "%" })
+            },
+            |left, right| left % right,
+        )
+    }
+
+    pub fn power(&mut self) -> Result<'gc, ()> {
+        self.arithmetic(
+            "**",
+            |left, right| {
+                if right < 0 {
+                    return Err(Error::NegativeExponent { exponent: right });
+                }
+                match (left, u32::try_from(right)) {
+                    (left, Ok(right)) => left
+                        .checked_pow(right)
+                        .ok_or(Error::IntegerOverflow { op: "**" }),
+                    // Only these bases stay in range for larger exponents.
+                    (0, Err(_)) => Ok(0),
+                    (1, Err(_)) => Ok(1),
+                    (-1, Err(_)) => Ok(if right % 2 == 0 { 1 } else { -1 }),
+                    (_, Err(_)) => Err(Error::IntegerOverflow { op: "**" }),
+                }
+            },
+            f64::powf,
+        )
+    }
+
+    pub fn bit_and(&mut self) -> Result<'gc, ()> {
+        self.bitwise("&", |left, right| Ok(left & right))
+    }
+
+    pub fn bit_or(&mut self) -> Result<'gc, ()> {
+        self.bitwise("|", |left, right| Ok(left | right))
+    }
+
+    pub fn bit_xor(&mut self) -> Result<'gc, ()> {
+        self.bitwise("^", |left, right| Ok(left ^ right))
+    }
+
+    pub fn shift_left(&mut self) -> Result<'gc, ()> {
+        self.bitwise("<<", |left, right| Ok(left << shift_amount(right)?))
+    }
+
+    pub fn shift_right(&mut self) -> Result<'gc, ()> {
+        self.bitwise(">>", |left, right| Ok(left >> shift_amount(right)?))
+    }
+
+    /// Applies `integer` if both operands are integers, or `float` if either
+    /// of them is a float.
+    fn arithmetic(
+        &mut self,
+        op: &'static str,
+        integer: fn(i64, i64) -> Result<'gc, i64>,
+        float: fn(f64, f64) -> f64,
+    ) -> Result<'gc, ()> {
+        let right_value = self.operand_stack.pop_value()?;
+        let left_value = self.operand_stack.pop_value()?;
+
+        let value =
+            arithmetic_value(op, &left_value, &right_value, integer, float)?;
+        Ok(self.operand_stack.push_value(value))
+    }
+
+    /// Applies `integer` to two integer operands.
+    fn bitwise(
+        &mut self,
+        op: &'static str,
+        integer: fn(i64, i64) -> Result<'gc, i64>,
+    ) -> Result<'gc, ()> {
+        let right_value = self.operand_stack.pop_value()?;
+        let left_value = self.operand_stack.pop_value()?;
+
+        let value = match (&left_value, &right_value) {
+            (Value::Integer(left), Value::Integer(right)) => {
+                Value::Integer(integer(*left, *right)?)
+            }
+            _ => {
+                return Err(Error::UnsupportedOperands {
+                    op,
+                    left: ValueKind::from(&left_value),
+                    right: ValueKind::from(&right_value),
+                });
+            }
+        };
+
+        self.operand_stack.push_value(value);
+        Ok(())
+    }
+
+    pub fn equal(&mut self) -> Result<'gc, ()> {
+        let right_value = self.operand_stack.pop_value()?;
+        let left_value = self.operand_stack.pop_value()?;
+
+        let value = Value::Boolean(left_value == right_value);
+        self.operand_stack.push_value(value);
+        Ok(())
+    }
+
+    pub fn not_equal(&mut self) -> Result<'gc, ()> {
+        let right_value = self.operand_stack.pop_value()?;
+        let left_value = self.operand_stack.pop_value()?;
+
+        let value = Value::Boolean(left_value != right_value);
+        self.operand_stack.push_value(value);
+        Ok(())
+    }
+
+    pub fn less(&mut self) -> Result<'gc, ()> {
+        self.compare("<", |ordering| ordering == Ordering::Less)
+    }
+
+    pub fn less_equal(&mut self) -> Result<'gc, ()> {
+        self.compare("<=", |ordering| ordering != Ordering::Greater)
+    }
+
+    pub fn greater(&mut self) -> Result<'gc, ()> {
+        self.compare(">", |ordering| ordering == Ordering::Greater)
+    }
+
+    pub fn greater_equal(&mut self) -> Result<'gc, ()> {
+        self.compare(">=", |ordering| ordering != Ordering::Less)
+    }
+
+    /// Pushes whether the ordering of the two operands matches. Unordered
+    /// numbers, such as a `NaN`, never match, but values of other kinds with
+    /// no order fail, at any depth.
+    fn compare(
+        &mut self,
+        op: &'static str,
+        matches: fn(Ordering) -> bool,
+    ) -> Result<'gc, ()> {
+        let right_value = self.operand_stack.pop_value()?;
+        let left_value = self.operand_stack.pop_value()?;
+
+        let ordering = match (&left_value, &right_value) {
+            (Value::Unit, Value::Unit)
+            | (Value::Boolean(_), Value::Boolean(_))
+            | (Value::Integer(_), Value::Integer(_))
+            | (Value::Integer(_), Value::Float(_))
+            | (Value::Float(_), Value::Integer(_))
+            | (Value::Float(_), Value::Float(_))
+            | (Value::String(_), Value::String(_))
+            | (Value::Tuple(_), Value::Tuple(_))
+            | (Value::List(_), Value::List(_)) => {
+                left_value.try_partial_cmp(&right_value)?
+            }
+            _ => {
+                return Err(Error::UnsupportedOperands {
+                    op,
+                    left: ValueKind::from(&left_value),
+                    right: ValueKind::from(&right_value),
+                });
+            }
+        };
+
+        let value = Value::Boolean(ordering.is_some_and(matches));
+        self.operand_stack.push_value(value);
+        Ok(())
+    }
+
+    pub fn not(&mut self) -> Result<'gc, ()> {
+        let value = match self.operand_stack.pop_value()? {
+            Value::Boolean(boolean) => Value::Boolean(!boolean),
+            value => {
+                return Err(Error::UnsupportedOperand {
+                    op: "!",
+                    operand: ValueKind::from(&value),
+                });
+            }
+        };
+
+        self.operand_stack.push_value(value);
+        Ok(())
+    }
+
+    pub fn negate(&mut self) -> Result<'gc, ()> {
+        let value = match self.operand_stack.pop_value()? {
+            Value::Integer(integer) => Value::Integer(
+                integer
+                    .checked_neg()
+                    .ok_or(Error::IntegerOverflow { op: "-" })?,
+            ),
+            Value::Float(float) => Value::Float(-float),
+            value => {
+                return Err(Error::UnsupportedOperand {
+                    op: "-",
+                    operand: ValueKind::from(&value),
+                });
+            }
+        };
+
+        self.operand_stack.push_value(value);
+        Ok(())
+    }
+}
+
+/// Applies `integer` to two integers, or `float` to two numbers of which at
+/// least one is a float.
+fn arithmetic_value<'gc>(
+    op: &'static str,
+    left: &Value<'gc>,
+    right: &Value<'gc>,
+    integer: fn(i64, i64) -> Result<'gc, i64>,
+    float: fn(f64, f64) -> f64,
+) -> Result<'gc, Value<'gc>> {
+    match (left, right) {
+        (Value::Integer(left), Value::Integer(right)) => {
+            Ok(Value::Integer(integer(*left, *right)?))
+        }
+        (Value::Integer(left), Value::Float(right)) => {
+            Ok(Value::Float(float(*left as f64, *right)))
+        }
+        (Value::Float(left), Value::Integer(right)) => {
+            Ok(Value::Float(float(*left, *right as f64)))
+        }
+        (Value::Float(left), Value::Float(right)) => {
+            Ok(Value::Float(float(*left, *right)))
+        }
+        (left, right) => Err(Error::UnsupportedOperands {
+            op,
+            left: ValueKind::from(left),
+            right: ValueKind::from(right),
+        }),
+    }
+}
+
+/// The number of bytes allocated for a string of `len` bytes.
+fn string_size(len: usize) -> usize {
+    mem::size_of::<std::string::String>() + len
+}
+
+/// The number of bytes allocated for `len` fields of a composite value.
+fn fields_size(len: usize) -> usize {
+    len * mem::size_of::<Value>()
+}
+
+/// The error for accessing a field of a value that has no such field.
+fn field_error<'gc>(value: &Value<'gc>) -> Error<'gc> {
+    match value {
+        Value::Tuple(_) => Error::InvalidKind {
+            expected: Kind::Tuple,
+            received: Kind::Map,
+        },
+        value => Error::UnsupportedOperand {
+            op: ".",
+            operand: ValueKind::from(value),
+        },
+    }
+}
+
+/// The number of bytes allocated for a captured scope of `len` variables.
+fn captured_scope_size(len: usize) -> usize {
+    mem::size_of::<CapturedScopeData>() + fields_size(len)
+}
+
+/// The number of bytes allocated for the scopes of a frame running `chunk`.
+fn frame_size(chunk: &Chunk) -> usize {
+    mem::size_of::<Value>()
+        * (chunk.local_scope_len() + chunk.captured_scope_len())
+}
+
+fn integer<'gc>(value: Value<'gc>) -> Result<'gc, i64> {
+    match value {
+        Value::Integer(integer) => Ok(integer),
+        value => Err(Error::InvalidValueKind {
+            expected: ValueKind::Integer,
+            received: ValueKind::from(&value),
+        }),
+    }
+}
+
+/// Checks that a shift moves by less than the width of an integer.
+fn shift_amount<'gc>(amount: i64) -> Result<'gc, u32> {
+    match u32::try_from(amount) {
+        Ok(amount) if amount < i64::BITS => Ok(amount),
+        _ => Err(Error::InvalidShift { amount }),
+    }
+}
+
+pub struct OperandStack<'gc> {
+    operands: Vec<Operand<'gc>>,
+    // Operands below the base belong to the calling frames.
+    base: usize,
+}
+
+impl<'gc> OperandStack<'gc> {
+    pub fn new() -> OperandStack<'gc> {
+        OperandStack {
+            operands: Vec::new(),
+            base: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.operands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operands.is_empty()
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    pub fn set_base(&mut self, base: usize) {
+        self.base = base;
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.operands.truncate(len);
+    }
+
+    fn pop(&mut self) -> Option<Operand<'gc>> {
+        if self.operands.len() > self.base {
+            self.operands.pop()
+        } else {
+            None
+        }
+    }
+
+    pub fn push_mod(&mut self, modu: Mod<'gc>) {
+        self.operands.push(Operand::Mod(modu))
+    }
+
+    pub fn pop_mod(&mut self) -> Result<'gc, Mod<'gc>> {
+        use self::Operand::*;
+
+        match self.pop() {
+            Some(Mod(modu)) => Ok(modu),
+            Some(operand) => Err(Error::InvalidOperandKind {
+                expected: OperandKind::Mod,
+                received: operand.into(),
+            }),
+            None => Err(Error::EmptyOperandStack),
+        }
+    }
+
+    /// Pops `len` values, returning them in the order they were pushed.
+    pub fn pop_values(&mut self, len: usize) -> Result<'gc, Vec<Value<'gc>>> {
+        let mut values = Vec::with_capacity(len);
+        for _ in 0..len {
+            values.push(self.pop_value()?);
+        }
+        values.reverse();
+        Ok(values)
+    }
+
+    pub fn push_fn(&mut self, fun: Fn<'gc>) {
+        self.push_value(Value::Fn(fun))
+    }
+
+    pub fn pop_fn(&mut self) -> Result<'gc, Fn<'gc>> {
+        match self.pop_value()? {
+            Value::Fn(fun) => Ok(fun),
+            value => Err(Error::InvalidValueKind {
+                expected: ValueKind::Fn,
+                received: ValueKind::from(&value),
+            }),
+        }
+    }
+
+    /// Pops a function to call, along with the captured scope it closes
+    /// over if it is a closure.
+    pub fn pop_callee(
+        &mut self,
+    ) -> Result<'gc, (Fn<'gc>, Option<CapturedScope<'gc>>)> {
+        match self.pop_value()? {
+            Value::Fn(fun) => Ok((fun, None)),
+            Value::Closure(closure) => {
+                Ok((closure.fun(), Some(closure.captured_scope())))
+            }
+            value => Err(Error::InvalidValueKind {
+                expected: ValueKind::Fn,
+                received: ValueKind::from(&value),
+            }),
+        }
+    }
+
+    pub fn push_value(&mut self, value: Value<'gc>) {
+        self.operands.push(Operand::Value(value))
+    }
+
+    pub fn pop_value(&mut self) -> Result<'gc, Value<'gc>> {
+        use self::Operand::*;
+
+        match self.pop() {
+            Some(Value(value)) => Ok(value),
+            Some(operand) => Err(Error::InvalidOperandKind {
+                expected: OperandKind::Value,
+                received: operand.into(),
+            }),
+            None => Err(Error::EmptyOperandStack),
+        }
+    }
+
+    pub fn pop_integer(&mut self) -> Result<'gc, i64> {
+        integer(self.pop_value()?)
+    }
+
+    pub fn pop_string(&mut self) -> Result<'gc, String<'gc>> {
+        match self.pop_value()? {
+            Value::String(string) => Ok(string),
+            value => Err(Error::InvalidValueKind {
+                expected: ValueKind::String,
+                received: ValueKind::from(&value),
+            }),
+        }
+    }
+
+    pub fn pop_boolean(&mut self) -> Result<'gc, bool> {
+        match self.pop_value()? {
+            Value::Boolean(boolean) => Ok(boolean),
+            value => Err(Error::InvalidValueKind {
+                expected: ValueKind::Boolean,
+                received: ValueKind::from(&value),
+            }),
+        }
+    }
+}
+
+pub enum Operand<'gc> {
+    Mod(Mod<'gc>),
+    Value(Value<'gc>),
+}
+
+#[derive(Debug)]
+pub enum OperandKind {
+    Mod,
+    Value,
+}
+
+impl<'gc> From<Operand<'gc>> for OperandKind {
+    fn from(operand: Operand<'gc>) -> OperandKind {
+        use self::OperandKind::*;
 
         match operand {
             Operand::Mod(_) => Mod,
-            Operand::Fn(_) => Fn,
             Operand::Value(_) => Value,
         }
     }
@@ -316,7 +1662,6 @@ impl fmt::Display for OperandKind {
 
         match self {
             Mod => write!(f, "mod"),
-            Fn => write!(f, "fn"),
             Value => write!(f, "value"),
         }
     }
@@ -325,15 +1670,78 @@ impl fmt::Display for OperandKind {
 // TODO: Remove all the `unwrap`s.
 #[cfg(test)]
 mod tests {
+    use std::mem;
+    use std::thread;
+
     use eko_gc::Arena;
 
     use crate::compiler::generator::ChunkBuilder;
-    use crate::core::fun::{External, Fn};
-    use crate::core::instr::Instr;
-    use crate::core::value::Value;
+    use crate::core::fun::{Chunk, External, Fn};
+    use crate::core::ident::Ident;
+    use crate::core::instr::{Fields, Instr};
+    use crate::core::modu::Mod;
+    use crate::core::typ::{self, EnumVariant, Kind, MapData, StructProto};
+    use crate::core::value::{
+        ClosureData, List, String, Tuple, Value, ValueKind,
+    };
+    use crate::engine::error::{CallError, Error};
     use crate::engine::frame::Frame;
 
-    use super::Machine;
+    use super::{captured_scope_size, frame_size, Machine};
+
+    fn chunk_fn<'gc>(
+        arena: &Arena<'gc>,
+        arity: u8,
+        chunk: Chunk<'gc>,
+    ) -> Fn<'gc> {
+        let modu = Mod::new(arena, Ident::new_number(0));
+        Fn::new_chunk(arena, modu, Ident::new_number(0), arity, chunk)
+    }
+
+    /// A function `down(n)` that calls itself until `n` is zero, and then
+    /// returns zero.
+    fn down<'gc>(arena: &Arena<'gc>) -> Fn<'gc> {
+        let modu = Mod::new(arena, Ident::new_number(0));
+        let ident = Ident::new_string(arena, "down".to_string());
+
+        let mut chunk = ChunkBuilder::new();
+        let n = chunk.next_var();
+        let base = chunk.label();
+        chunk.instr(Instr::PushVar { var: n });
+        chunk.instr(Instr::PushValue {
+            value: Value::Integer(0),
+        });
+        chunk.instr(Instr::Equal);
+        chunk.jump_if_true(base);
+        chunk.instr(Instr::PushMod { modu: modu.clone() });
+        chunk.instr(Instr::GetFn {
+            ident: ident.clone(),
+        });
+        chunk.instr(Instr::PushVar { var: n });
+        chunk.instr(Instr::PushValue {
+            value: Value::Integer(1),
+        });
+        chunk.instr(Instr::Subtract);
+        chunk.instr(Instr::Call {
+            arity: 1,
+            is_method: false,
+        });
+        chunk.instr(Instr::Return);
+        chunk.set_label(base);
+        chunk.instr(Instr::PushValue {
+            value: Value::Integer(0),
+        });
+
+        let fun = Fn::new_chunk(
+            arena,
+            modu.clone(),
+            ident.clone(),
+            1,
+            chunk.build(arena),
+        );
+        modu.set_fn(ident, fun.clone());
+        fun
+    }
 
     #[test]
     fn push_value() {
@@ -357,54 +1765,523 @@ mod tests {
         let var = chunk.next_var();
         let chunk = chunk.build(&arena);
 
-        let frame = Frame::new(&arena, chunk);
+        let modu = Mod::new(&arena, Ident::new_number(0));
+        let fun =
+            Fn::new_chunk(&arena, modu, Ident::new_number(0), 0, chunk.clone());
+        machine.frames.push(Frame::new(&arena, fun, chunk, 0));
+
+        machine.push_value(Value::Integer(2));
+        machine.pop_var(var).unwrap();
+        machine.push_var(var).unwrap();
+
+        assert_eq!(
+            machine.operand_stack.pop_value().unwrap(),
+            Value::Integer(2),
+        );
+    }
+
+    #[test]
+    fn call_chunk() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        let mut chunk = ChunkBuilder::new();
+        chunk.instr(Instr::PushValue {
+            value: Value::Integer(1),
+        });
+        chunk.instr(Instr::Pop);
+        chunk.instr(Instr::PushValue {
+            value: Value::Integer(3),
+        });
+        let chunk = chunk.build(&arena);
+
+        let modu = Mod::new(&arena, Ident::new_number(0));
+        let ident = Ident::new_number(0);
+
+        machine.push_value(Value::Integer(2));
+        machine.push_fn(Fn::new_chunk(&arena, modu, ident, 0, chunk));
+        machine.call(0, false).unwrap();
+
+        assert_eq!(
+            machine.operand_stack.pop_value().unwrap(),
+            Value::Integer(3),
+        );
+        assert_eq!(
+            machine.operand_stack.pop_value().unwrap(),
+            Value::Integer(2),
+        );
+    }
+
+    #[test]
+    fn deep_recursion() {
+        let arena = Arena::new();
+        let down = down(&arena);
+
+        let mut machine = Machine::new(&arena).max_call_depth(100_001);
+        machine.push_fn(down.clone());
+        machine.push_value(Value::Integer(100_000));
+        machine.call(1, false).unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(0));
+
+        let mut machine = Machine::new(&arena).max_call_depth(100);
+        machine.push_fn(down.clone());
+        machine.push_value(Value::Integer(100));
+        match machine.call(1, false).map_err(CallError::into_error) {
+            Err(Error::CallDepthExceeded {
+                max_call_depth: 100,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // The machine is still usable after the failed call.
+        machine.push_fn(down);
+        machine.push_value(Value::Integer(99));
+        machine.call(1, false).unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(0));
+    }
+
+    #[test]
+    fn fuel() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena).fuel(2);
+
+        let mut chunk = ChunkBuilder::new();
+        chunk.instr(Instr::PushValue {
+            value: Value::Integer(1),
+        });
+        chunk.instr(Instr::Pop);
+        chunk.instr(Instr::PushValue {
+            value: Value::Integer(3),
+        });
+        let chunk = chunk.build(&arena);
+
+        let modu = Mod::new(&arena, Ident::new_number(0));
+        let ident = Ident::new_number(0);
+
+        machine.push_value(Value::Integer(2));
+        machine.push_fn(Fn::new_chunk(&arena, modu, ident, 0, chunk));
+        match machine.call(0, false) {
+            Err(error) => {
+                assert!(matches!(error.error(), Error::OutOfFuel));
+                assert_eq!(error.stack_trace()[0].instr_index(), 1);
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(machine.is_suspended());
+        assert_eq!(machine.remaining_fuel(), Some(0));
+
+        machine.add_fuel(5);
+        machine.resume().unwrap();
+        assert!(!machine.is_suspended());
+        assert_eq!(machine.remaining_fuel(), Some(4));
+
+        assert_eq!(machine.pop().unwrap(), Value::Integer(3));
+        assert_eq!(machine.pop().unwrap(), Value::Integer(2));
+
+        match machine.resume().map_err(CallError::into_error) {
+            Err(Error::NoSuspendedCall) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn fuel_top_ups() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena).fuel(100);
+
+        machine.push_fn(down(&arena));
+        machine.push_value(Value::Integer(50));
+        let mut top_ups = 0;
+        let mut result = machine.call(1, false);
+        while let Err(error) = result {
+            assert!(matches!(error.error(), Error::OutOfFuel));
+            top_ups += 1;
+            machine.add_fuel(100);
+            result = machine.resume();
+        }
+        assert!(top_ups > 0);
+        assert_eq!(machine.pop().unwrap(), Value::Integer(0));
+        assert!(machine.pop().is_err());
+    }
+
+    #[test]
+    fn interrupt() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena).fuel(10);
+
+        let mut chunk = ChunkBuilder::new();
+        let start = chunk.label();
+        chunk.set_label(start);
+        chunk.jump(start);
+        let spin = chunk_fn(&arena, 0, chunk.build(&arena));
+
+        let mut chunk = ChunkBuilder::new();
+        chunk.instr(Instr::PushValue {
+            value: Value::Integer(1),
+        });
+        let one = chunk_fn(&arena, 0, chunk.build(&arena));
+
+        // A stale interrupt doesn't affect a later call.
+        let handle = machine.interrupt_handle();
+        thread::spawn(move || handle.interrupt()).join().unwrap();
+        machine.push_fn(one);
+        machine.call(0, false).unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(1));
+
+        // An interrupt stops a running call, here one suspended for fuel.
+        machine.push_fn(spin);
+        match machine.call(0, false) {
+            Err(error) => assert!(matches!(error.error(), Error::OutOfFuel)),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        let handle = machine.interrupt_handle();
+        thread::spawn(move || handle.interrupt()).join().unwrap();
+        machine.add_fuel(10);
+        match machine.resume() {
+            Err(error) => {
+                assert!(matches!(error.error(), Error::Interrupted));
+                assert_eq!(error.stack_trace()[0].instr_index(), 0);
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(!machine.is_suspended());
+        assert_eq!(machine.remaining_fuel(), Some(10));
+    }
+
+    #[test]
+    fn heap_limit() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena).max_heap_size(64);
+        let string = Value::String(String::from_str(
+            &arena,
+            "a string constant of 32 bytes...",
+        ));
+
+        // Each load of a string constant is accounted.
+        let mut chunk = ChunkBuilder::new();
+        for _ in 0..2 {
+            chunk.instr(Instr::PushValue {
+                value: string.clone(),
+            });
+            chunk.instr(Instr::Pop);
+        }
+        chunk.instr(Instr::PushValue {
+            value: Value::Integer(0),
+        });
+        machine.push_fn(chunk_fn(&arena, 0, chunk.build(&arena)));
+        match machine.call(0, false).map_err(CallError::into_error) {
+            Err(Error::OutOfMemory { max_heap_size: 64 }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(machine.heap_size(), 0);
+        assert_eq!(machine.heap_objects(), 0);
+
+        // The limit applies to each call on its own.
+        let mut chunk = ChunkBuilder::new();
+        chunk.instr(Instr::PushValue { value: string });
+        let fun = chunk_fn(&arena, 0, chunk.build(&arena));
+        for _ in 0..2 {
+            machine.push_fn(fun.clone());
+            machine.call(0, false).unwrap();
+            machine.pop().unwrap();
+            assert_eq!(machine.heap_size(), 0);
+        }
+
+        // Closures account for the captured scope they keep alive.
+        let size = mem::size_of::<ClosureData>() + captured_scope_size(0);
+        let mut machine = Machine::new(&arena).max_heap_size(size - 1);
+        let mut chunk = ChunkBuilder::new();
+        chunk.instr(Instr::PushFn { fun });
+        chunk.instr(Instr::MakeClosure);
+        machine.push_fn(chunk_fn(&arena, 0, chunk.build(&arena)));
+        match machine.call(0, false).map_err(CallError::into_error) {
+            Err(Error::OutOfMemory { .. }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // Indexing out of bounds allocates nothing.
+        let mut machine = Machine::new(&arena).max_heap_size(0);
+        machine.push_value(Value::String(String::from_str(&arena, "abc")));
+        machine.push_value(Value::Integer(3));
+        match machine.index() {
+            Err(Error::IndexOutOfBounds { index: 3, len: 3 }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(machine.heap_size(), 0);
+    }
+
+    #[test]
+    fn heap_frames() {
+        let arena = Arena::new();
+
+        let mut chunk = ChunkBuilder::new();
+        let var = chunk.next_var();
+        chunk.instr(Instr::PushVar { var });
+        let inner = chunk.build(&arena);
+        let frame_size = frame_size(&inner);
+        let inner = chunk_fn(&arena, 1, inner);
+
+        let mut chunk = ChunkBuilder::new();
+        chunk.next_var();
+        for arg in 0..2 {
+            chunk.instr(Instr::PushFn { fun: inner.clone() });
+            chunk.instr(Instr::PushValue {
+                value: Value::Integer(arg),
+            });
+            chunk.instr(Instr::Call {
+                arity: 1,
+                is_method: false,
+            });
+        }
+        chunk.instr(Instr::Add);
+        let outer = chunk_fn(&arena, 0, chunk.build(&arena));
+
+        // Only two frames are live at a time, as the first call to `inner`
+        // is released when it returns.
+        let mut machine = Machine::new(&arena).max_heap_size(frame_size * 2);
+        machine.push_fn(outer.clone());
+        machine.call(0, false).unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(1));
+        assert_eq!(machine.heap_size(), 0);
+
+        let mut machine = Machine::new(&arena).max_heap_size(frame_size);
+        machine.push_fn(outer.clone());
+        match machine.call(0, false).map_err(CallError::into_error) {
+            Err(Error::OutOfMemory { .. }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(machine.heap_size(), 0);
+
+        // A frame that fails to be set up is never accounted.
+        let mut machine = Machine::new(&arena).fuel(0);
+        machine.push_fn(outer);
+        machine.call(0, false).unwrap_err();
+        assert!(machine.is_suspended());
+        assert_eq!(machine.heap_size(), frame_size);
+
+        let chunk = Chunk::new(&arena, 0, 2, Vec::new(), Vec::new());
+        machine.push_fn(chunk_fn(&arena, 1, chunk));
+        machine.push_value(Value::Integer(0));
+        match machine.call(1, false).map_err(CallError::into_error) {
+            Err(Error::VarNotFound { var: 0 }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(machine.heap_size(), frame_size);
+        assert_eq!(machine.heap_objects(), 1);
+    }
+
+    #[test]
+    fn stack_trace() {
+        let arena = Arena::new();
+        let root = Mod::new(&arena, Ident::new_number(0));
+        let m = Mod::with_parent_mod(
+            &arena,
+            Ident::new_string(&arena, "m".to_string()),
+            root.clone(),
+        );
+        fn fun<'gc>(
+            arena: &Arena<'gc>,
+            modu: &Mod<'gc>,
+            name: &str,
+            chunk: ChunkBuilder<'gc>,
+        ) -> Fn<'gc> {
+            let ident = Ident::new_string(arena, name.to_string());
+            Fn::new_chunk(arena, modu.clone(), ident, 0, chunk.build(arena))
+        }
+
+        let mut chunk = ChunkBuilder::new();
+        chunk.set_line(Some(7));
+        chunk.instr(Instr::PushValue {
+            value: Value::Integer(1),
+        });
+        chunk.instr(Instr::PushValue {
+            value: Value::Boolean(true),
+        });
+        chunk.instr(Instr::Less);
+        let fail = fun(&arena, &m, "fail", chunk);
+
+        let mut chunk = ChunkBuilder::new();
+        chunk.set_line(Some(11));
+        chunk.instr(Instr::PushFn { fun: fail });
+        chunk.instr(Instr::Call {
+            arity: 0,
+            is_method: false,
+        });
+        let helper = fun(&arena, &root, "helper", chunk);
+
+        let mut chunk = ChunkBuilder::new();
+        chunk.set_line(Some(2));
+        chunk.instr(Instr::PushFn { fun: helper });
+        chunk.instr(Instr::Call {
+            arity: 0,
+            is_method: false,
+        });
+        let main = fun(&arena, &root, "main", chunk);
+
+        let mut machine = Machine::new(&arena);
+        machine.push_fn(main);
+        let error = machine.call(0, false).unwrap_err();
+
+        match error.error() {
+            Error::UnsupportedOperands { op: "<", .. } => {}
+            error => panic!("unexpected error: {:?}", error),
+        }
+
+        let frames: Vec<_> = error
+            .stack_trace()
+            .iter()
+            .map(|frame| {
+                let mod_path: Vec<_> =
+                    frame.mod_path().iter().map(Ident::to_string).collect();
+                (
+                    mod_path.join("::"),
+                    frame.ident().to_string(),
+                    frame.instr_index(),
+                    frame.line(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            frames,
+            [
+                ("0::m".to_string(), "fail".to_string(), 2, Some(7)),
+                ("0".to_string(), "helper".to_string(), 1, Some(11)),
+                ("0".to_string(), "main".to_string(), 1, Some(2)),
+            ],
+        );
+        assert_eq!(
+            error.to_string(),
+            "unsupported operands for `<`: integer and boolean\n   \
+             0: 0::m::fail at instruction 2, line 7\n   \
+             1: 0::helper at instruction 1, line 11\n   \
+             2: 0::main at instruction 1, line 2"
+        );
+
+        // The machine is left without any frames.
+        assert!(machine.pop().is_err());
+    }
+
+    #[test]
+    fn call_non_callable() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        machine.push_value(Value::Integer(1));
+        match machine.call(0, false).map_err(CallError::into_error) {
+            Err(Error::InvalidValueKind {
+                expected: ValueKind::Fn,
+                received: ValueKind::Integer,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn ret() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        fn call<'gc>(
+            arena: &Arena<'gc>,
+            machine: &mut Machine<'_, 'gc>,
+            instrs: Vec<Instr<'gc>>,
+        ) -> Result<(), Error<'gc>> {
+            let mut chunk = ChunkBuilder::new();
+            for instr in instrs {
+                chunk.instr(instr);
+            }
+            let chunk = chunk.build(arena);
+
+            let modu = Mod::new(arena, Ident::new_number(0));
+            let ident = Ident::new_number(0);
+
+            machine.push_fn(Fn::new_chunk(arena, modu, ident, 0, chunk));
+            machine.call(0, false).map_err(CallError::into_error)
+        }
+        let push = |integer| Instr::PushValue {
+            value: Value::Integer(integer),
+        };
+
+        machine.push_value(Value::Integer(1));
+
+        // Leftover operands are discarded by `Return`, and anything after it
+        // is not run.
+        call(
+            &arena,
+            &mut machine,
+            vec![push(2), push(3), Instr::Return, push(4)],
+        )
+        .unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(3));
+
+        match call(&arena, &mut machine, vec![push(2), push(3)]) {
+            Err(Error::LeftoverOperands { len: 2 }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        machine.operand_stack.truncate(1);
+
+        match call(
+            &arena,
+            &mut machine,
+            vec![Instr::Pop, push(2), Instr::Return],
+        ) {
+            Err(Error::EmptyOperandStack) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        machine.operand_stack.truncate(1);
 
-        machine.push_value(Value::Integer(2));
-        machine.pop_var(&frame, var).unwrap();
-        machine.push_var(&frame, var).unwrap();
+        match call(&arena, &mut machine, vec![]) {
+            Err(Error::EmptyOperandStack) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
 
-        assert_eq!(
-            machine.operand_stack.pop_value().unwrap(),
-            Value::Integer(2),
-        );
+        assert_eq!(machine.pop().unwrap(), Value::Integer(1));
+        assert!(machine.pop().is_err());
     }
 
     #[test]
-    fn call_chunk() {
+    fn call_external() {
         let arena = Arena::new();
         let mut machine = Machine::new(&arena);
 
-        let mut chunk = ChunkBuilder::new();
-        chunk.instr(Instr::Pop);
-        chunk.instr(Instr::PushValue {
-            value: Value::Integer(3),
-        });
-        let chunk = chunk.build(&arena);
+        let modu = Mod::new(&arena, Ident::new_number(0));
+        let ident = Ident::new_number(0);
+        let external = External::new(&arena, |_| Value::Integer(7));
 
-        machine.push_value(Value::Integer(2));
-        machine.push_fn(Fn::new_chunk(&arena, 0, chunk));
+        machine.push_fn(Fn::new_external(&arena, modu, ident, 0, external));
         machine.call(0, false).unwrap();
 
         assert_eq!(
             machine.operand_stack.pop_value().unwrap(),
-            Value::Integer(3),
+            Value::Integer(7),
         );
     }
 
     #[test]
-    fn call_external() {
+    fn get_fn() {
         let arena = Arena::new();
         let mut machine = Machine::new(&arena);
 
+        let modu = Mod::new(&arena, Ident::new_number(0));
+        let ident = Ident::new_string(&arena, "seven".to_string());
         let external = External::new(&arena, |_| Value::Integer(7));
+        let fun =
+            Fn::new_external(&arena, modu.clone(), ident.clone(), 0, external);
+        modu.set_fn(ident.clone(), fun);
 
-        machine.push_fn(Fn::new_external(&arena, 0, external));
+        machine.push_mod(modu.clone());
+        machine.get_fn(ident).unwrap();
         machine.call(0, false).unwrap();
 
         assert_eq!(
             machine.operand_stack.pop_value().unwrap(),
             Value::Integer(7),
         );
+
+        machine.push_mod(modu);
+        assert!(machine
+            .get_fn(Ident::new_string(&arena, "eight".to_string()))
+            .is_err());
     }
 
     #[test]
@@ -466,4 +2343,925 @@ mod tests {
             Value::Integer(3),
         );
     }
+
+    #[test]
+    fn multiply_integer_float() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        machine.push_value(Value::Integer(3));
+        machine.push_value(Value::Float(0.5));
+        machine.multiply().unwrap();
+
+        assert_eq!(
+            machine.operand_stack.pop_value().unwrap(),
+            Value::Float(1.5),
+        );
+    }
+
+    #[test]
+    fn arithmetic_errors() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        machine.push_value(Value::Integer(1));
+        machine.push_value(Value::Boolean(true));
+        match machine.add() {
+            Err(Error::UnsupportedOperands {
+                op: "+",
+                left: ValueKind::Integer,
+                right: ValueKind::Boolean,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        machine.push_value(Value::Integer(1));
+        machine.push_value(Value::Integer(0));
+        match machine.divide() {
+            Err(Error::DivisionByZero) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        machine.push_value(Value::Float(1.0));
+        machine.push_value(Value::Integer(0));
+        machine.divide().unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Float(f64::INFINITY));
+
+        let overflows = [
+            ("+", i64::MAX, 1),
+            ("-", i64::MIN, 1),
+            ("*", i64::MAX, 2),
+            ("/", i64::MIN, -1),
+        ];
+        for &(op, left, right) in overflows.iter() {
+            machine.push_value(Value::Integer(left));
+            machine.push_value(Value::Integer(right));
+            let result = match op {
+                "+" => machine.add(),
+                "-" => machine.subtract(),
+                "*" => machine.multiply(),
+                _ => machine.divide(),
+            };
+            match result {
+                Err(Error::IntegerOverflow { op: result_op })
+                    if result_op == op => {}
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+
+        machine.push_value(Value::Integer(i64::MIN));
+        match machine.negate() {
+            Err(Error::IntegerOverflow { op: "-" }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn extended_arithmetic() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        let cases = [
+            (
+                "//",
+                Value::Integer(7),
+                Value::Integer(2),
+                Value::Integer(3),
+            ),
+            (
+                "//",
+                Value::Integer(-7),
+                Value::Integer(2),
+                Value::Integer(-4),
+            ),
+            (
+                "//",
+                Value::Float(-7.0),
+                Value::Integer(2),
+                Value::Float(-4.0),
+            ),
+            (
+                "%",
+                Value::Integer(-7),
+                Value::Integer(3),
+                Value::Integer(-1),
+            ),
+            ("%", Value::Float(7.5), Value::Integer(2), Value::Float(1.5)),
+            (
+                "**",
+                Value::Integer(3),
+                Value::Integer(4),
+                Value::Integer(81),
+            ),
+            (
+                "**",
+                Value::Integer(4),
+                Value::Float(0.5),
+                Value::Float(2.0),
+            ),
+            (
+                "**",
+                Value::Integer(1),
+                Value::Integer(5_000_000_000),
+                Value::Integer(1),
+            ),
+            (
+                "**",
+                Value::Integer(-1),
+                Value::Integer(5_000_000_001),
+                Value::Integer(-1),
+            ),
+            (
+                "**",
+                Value::Integer(0),
+                Value::Integer(i64::MAX),
+                Value::Integer(0),
+            ),
+        ];
+        for (op, left, right, expected) in cases.iter().cloned() {
+            machine.push_value(left);
+            machine.push_value(right);
+            match op {
+                "//" => machine.floor_divide().unwrap(),
+                "%" => machine.remainder().unwrap(),
+                _ => machine.power().unwrap(),
+            }
+            assert_eq!(machine.pop().unwrap(), expected, "{}", op);
+        }
+
+        machine.push_value(Value::Integer(1));
+        machine.push_value(Value::Integer(0));
+        match machine.remainder() {
+            Err(Error::DivisionByZero) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        machine.push_value(Value::Integer(2));
+        machine.push_value(Value::Integer(-1));
+        match machine.power() {
+            Err(Error::NegativeExponent { exponent: -1 }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        for exponent in [64, 5_000_000_000].iter().cloned() {
+            machine.push_value(Value::Integer(2));
+            machine.push_value(Value::Integer(exponent));
+            match machine.power() {
+                Err(Error::IntegerOverflow { op: "**" }) => {}
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn bitwise() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        machine.push_value(Value::Integer(0b1100));
+        machine.push_value(Value::Integer(0b1010));
+        machine.bit_and().unwrap();
+        machine.push_value(Value::Integer(0b0001));
+        machine.bit_or().unwrap();
+        machine.push_value(Value::Integer(0b1111));
+        machine.bit_xor().unwrap();
+        machine.push_value(Value::Integer(3));
+        machine.shift_left().unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(0b0011_0000));
+
+        machine.push_value(Value::Integer(-16));
+        machine.push_value(Value::Integer(2));
+        machine.shift_right().unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(-4));
+
+        for &amount in [-1, 64].iter() {
+            machine.push_value(Value::Integer(1));
+            machine.push_value(Value::Integer(amount));
+            match machine.shift_left() {
+                Err(Error::InvalidShift { amount: result }) => {
+                    assert_eq!(result, amount)
+                }
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+
+        machine.push_value(Value::Float(1.0));
+        machine.push_value(Value::Integer(1));
+        match machine.bit_or() {
+            Err(Error::UnsupportedOperands {
+                op: "|",
+                left: ValueKind::Float,
+                right: ValueKind::Integer,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn jumps() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        let mut chunk = ChunkBuilder::new();
+        let flag = chunk.next_var();
+        let count = chunk.next_var();
+        let start_label = chunk.label();
+        let end_label = chunk.label();
+        chunk.instr(Instr::PushValue {
+            value: Value::Boolean(true),
+        });
+        chunk.instr(Instr::PopVar { var: flag });
+        chunk.instr(Instr::PushValue {
+            value: Value::Integer(0),
+        });
+        chunk.instr(Instr::PopVar { var: count });
+        chunk.set_label(start_label);
+        chunk.instr(Instr::PushVar { var: flag });
+        chunk.jump_if_false(end_label);
+        chunk.instr(Instr::PushVar { var: count });
+        chunk.instr(Instr::PushValue {
+            value: Value::Integer(1),
+        });
+        chunk.instr(Instr::Add);
+        chunk.instr(Instr::PopVar { var: count });
+        chunk.instr(Instr::PushValue {
+            value: Value::Boolean(false),
+        });
+        chunk.instr(Instr::PopVar { var: flag });
+        chunk.jump(start_label);
+        chunk.set_label(end_label);
+        chunk.instr(Instr::PushVar { var: count });
+        let chunk = chunk.build(&arena);
+
+        let modu = Mod::new(&arena, Ident::new_number(0));
+        let ident = Ident::new_number(0);
+
+        machine.push_fn(Fn::new_chunk(&arena, modu, ident, 0, chunk));
+        machine.call(0, false).unwrap();
+
+        assert_eq!(
+            machine.operand_stack.pop_value().unwrap(),
+            Value::Integer(1),
+        );
+        assert!(machine.operand_stack.pop_value().is_err());
+    }
+
+    #[test]
+    fn jump_errors() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        let chunk = ChunkBuilder::new().build(&arena);
+        assert!(machine.jump(0).is_err());
+        let modu = Mod::new(&arena, Ident::new_number(0));
+        let fun =
+            Fn::new_chunk(&arena, modu, Ident::new_number(0), 0, chunk.clone());
+        machine.frames.push(Frame::new(&arena, fun, chunk, 0));
+
+        machine.push_value(Value::Integer(1));
+        assert!(machine.jump_if_true(0).is_err());
+        assert!(machine.jump(1).is_err());
+        assert!(machine.jump(0).is_ok());
+    }
+
+    #[test]
+    fn equal() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        machine.push_value(Value::Integer(2));
+        machine.push_value(Value::Float(2.0));
+        machine.equal().unwrap();
+
+        let hello = |arena| String::new(arena, "hello".to_string());
+        machine.push_value(Value::String(hello(&arena)));
+        machine.push_value(Value::String(hello(&arena)));
+        machine.not_equal().unwrap();
+
+        machine.push_value(Value::Boolean(true));
+        machine.push_value(Value::Integer(1));
+        machine.equal().unwrap();
+
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(false));
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(false));
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(true));
+    }
+
+    #[test]
+    fn structural_compare() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+        let pair = |left, right| {
+            Value::Tuple(Tuple::new(
+                &arena,
+                vec![
+                    Value::String(String::new(&arena, left)),
+                    Value::Integer(right),
+                ],
+            ))
+        };
+
+        machine.push_value(pair("a".into(), 1));
+        machine.push_value(pair("a".into(), 1));
+        machine.equal().unwrap();
+
+        machine.push_value(pair("a".into(), 2));
+        machine.push_value(pair("b".into(), 1));
+        machine.less().unwrap();
+
+        machine.push_value(pair("a".into(), 1));
+        machine.push_value(pair("a".into(), 2));
+        machine.greater_equal().unwrap();
+
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(false));
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(true));
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(true));
+    }
+
+    #[test]
+    fn compare() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        machine.push_value(Value::Integer(2));
+        machine.push_value(Value::Float(2.5));
+        machine.less().unwrap();
+
+        machine.push_value(Value::Float(2.0));
+        machine.push_value(Value::Integer(2));
+        machine.less_equal().unwrap();
+
+        machine.push_value(Value::String(String::new(&arena, "b".into())));
+        machine.push_value(Value::String(String::new(&arena, "a".into())));
+        machine.greater().unwrap();
+
+        machine.push_value(Value::Float(f64::NAN));
+        machine.push_value(Value::Float(1.0));
+        machine.greater_equal().unwrap();
+
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(false));
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(true));
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(true));
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(true));
+
+        machine.push_value(Value::Integer(1));
+        machine.push_value(Value::Boolean(true));
+        assert!(machine.less().is_err());
+
+        let tuple = |value| Value::Tuple(Tuple::new(&arena, vec![value]));
+        machine.push_value(Value::Boolean(true));
+        machine.push_value(Value::Boolean(false));
+        machine.less().unwrap();
+        machine.push_value(tuple(Value::Boolean(false)));
+        machine.push_value(tuple(Value::Boolean(true)));
+        machine.less().unwrap();
+        machine.push_value(Value::Unit);
+        machine.push_value(Value::Unit);
+        machine.less_equal().unwrap();
+
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(true));
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(true));
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(false));
+
+        machine.push_value(tuple(Value::Integer(1)));
+        machine
+            .push_value(tuple(Value::String(String::new(&arena, "a".into()))));
+        match machine.less() {
+            Err(Error::UnorderedValues {
+                left: ValueKind::Integer,
+                right: ValueKind::String,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn not_negate() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        machine.push_value(Value::Boolean(false));
+        machine.not().unwrap();
+        machine.push_value(Value::Integer(3));
+        machine.negate().unwrap();
+        machine.push_value(Value::Float(1.5));
+        machine.negate().unwrap();
+
+        assert_eq!(machine.pop().unwrap(), Value::Float(-1.5));
+        assert_eq!(machine.pop().unwrap(), Value::Integer(-3));
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(true));
+
+        machine.push_value(Value::Integer(1));
+        assert!(machine.not().is_err());
+        machine.push_value(Value::Boolean(true));
+        assert!(machine.negate().is_err());
+    }
+
+    #[test]
+    fn fields() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        let tuple = Value::Tuple(Tuple::new(
+            &arena,
+            vec![Value::Integer(1), Value::Integer(2)],
+        ));
+
+        machine.push_value(tuple.clone());
+        machine.push_value(Value::Integer(3));
+        machine.set_field(Ident::new_number(1)).unwrap();
+        machine.push_value(tuple.clone());
+        machine.get_field(Ident::new_number(1)).unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(3));
+
+        machine.push_value(tuple.clone());
+        match machine.get_field(Ident::new_number(2)) {
+            Err(Error::InvalidField {
+                field: Ident::Number(2),
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let name = Ident::new_string(&arena, "x".to_string());
+        machine.push_value(tuple);
+        match machine.get_field(name.clone()) {
+            Err(Error::InvalidKind {
+                expected: Kind::Tuple,
+                received: Kind::Map,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        machine.push_value(Value::Integer(1));
+        match machine.get_field(name) {
+            Err(Error::UnsupportedOperand {
+                op: ".",
+                operand: ValueKind::Integer,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn make_tuple() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        machine.push_value(Value::Integer(1));
+        machine.push_value(Value::Integer(2));
+        machine.make_tuple(2).unwrap();
+
+        match machine.pop().unwrap() {
+            Value::Tuple(tuple) => {
+                assert_eq!(tuple.field(0).unwrap(), Value::Integer(1));
+                assert_eq!(tuple.field(1).unwrap(), Value::Integer(2));
+            }
+            value => panic!("unexpected value: {:?}", value),
+        }
+
+        machine.push_value(Value::Integer(1));
+        match machine.make_tuple(2) {
+            Err(Error::EmptyOperandStack) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn make_struct() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        let x = Ident::new_string(&arena, "x".to_string());
+        let y = Ident::new_string(&arena, "y".to_string());
+        let point = typ::Struct::new(
+            &arena,
+            Ident::new_string(&arena, "Point".to_string()),
+            StructProto::Map(MapData::new(vec![x.clone(), y.clone()])),
+        );
+
+        machine.push_value(Value::Integer(2));
+        machine.push_value(Value::Integer(1));
+        let fields = Fields::Map(vec![y.clone(), x.clone()]);
+        machine.make_struct(point.clone(), fields).unwrap();
+
+        match machine.pop().unwrap() {
+            Value::Struct(strukt) => {
+                assert_eq!(
+                    strukt.map_field(x.clone()).unwrap(),
+                    Value::Integer(1)
+                );
+                assert_eq!(strukt.map_field(y).unwrap(), Value::Integer(2));
+            }
+            value => panic!("unexpected value: {:?}", value),
+        }
+
+        machine.push_value(Value::Integer(1));
+        match machine.make_struct(point.clone(), Fields::Map(vec![x])) {
+            Err(Error::MissingField { .. }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        machine.push_value(Value::Integer(1));
+        match machine.make_struct(point, Fields::Tuple(1)) {
+            Err(Error::InvalidKind {
+                expected: Kind::Tuple,
+                received: Kind::Map,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let pair = typ::Struct::new(
+            &arena,
+            Ident::new_string(&arena, "Pair".to_string()),
+            StructProto::Tuple(2),
+        );
+        machine.push_value(Value::Integer(1));
+        machine.push_value(Value::Integer(2));
+        machine.push_value(Value::Integer(3));
+        match machine.make_struct(pair, Fields::Tuple(3)) {
+            Err(Error::InvalidField {
+                field: Ident::Number(2),
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn make_enum() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        let option = typ::Enum::new(
+            &arena,
+            Ident::new_string(&arena, "Option".to_string()),
+            vec![
+                EnumVariant::new(
+                    Ident::new_string(&arena, "None".to_string()),
+                    StructProto::Tuple(0),
+                ),
+                EnumVariant::new(
+                    Ident::new_string(&arena, "Some".to_string()),
+                    StructProto::Tuple(1),
+                ),
+            ],
+        );
+
+        machine.push_value(Value::Integer(1));
+        machine
+            .make_enum(option.clone(), 1, Fields::Tuple(1))
+            .unwrap();
+
+        match machine.pop().unwrap() {
+            Value::Enum(enu) => {
+                assert_eq!(enu.tuple_field(0).unwrap(), Value::Integer(1));
+            }
+            value => panic!("unexpected value: {:?}", value),
+        }
+
+        match machine.make_enum(option, 2, Fields::Tuple(0)) {
+            Err(Error::InvalidVariant { variant: 2 }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn lists() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+
+        machine.push_value(Value::Integer(1));
+        machine.push_value(Value::Integer(2));
+        machine.make_list(2).unwrap();
+        let list = machine.pop().unwrap();
+
+        machine.push_value(list.clone());
+        machine.push_value(Value::Integer(3));
+        machine.list_push().unwrap();
+        machine.push_value(list.clone());
+        machine.push_value(Value::Integer(0));
+        machine.push_value(Value::Integer(4));
+        machine.set_index().unwrap();
+
+        machine.push_value(list.clone());
+        machine.push_value(Value::Integer(1));
+        machine.push_value(Value::Integer(3));
+        machine.slice().unwrap();
+        let slice = machine.pop().unwrap();
+
+        machine.push_value(list.clone());
+        machine.list_pop().unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(3));
+        machine.push_value(list.clone());
+        machine.len().unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(2));
+        machine.push_value(list.clone());
+        machine.push_value(Value::Integer(0));
+        machine.index().unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(4));
+
+        machine.push_value(slice.clone());
+        machine.len().unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(2));
+        machine.push_value(slice);
+        machine.push_value(Value::Integer(1));
+        machine.index().unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(3));
+
+        machine.push_value(list.clone());
+        machine.push_value(Value::Integer(-1));
+        match machine.index() {
+            Err(Error::IndexOutOfBounds { index: -1, len: 2 }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        machine.push_value(list.clone());
+        machine.push_value(Value::Integer(2));
+        machine.push_value(Value::Integer(5));
+        match machine.set_index() {
+            Err(Error::IndexOutOfBounds { index: 2, len: 2 }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        machine.push_value(list);
+        machine.push_value(Value::Integer(2));
+        machine.push_value(Value::Integer(1));
+        match machine.slice() {
+            Err(Error::InvalidSlice {
+                start: 2,
+                end: 1,
+                len: 2,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        machine.make_list(0).unwrap();
+        match machine.list_pop() {
+            Err(Error::EmptyList) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        machine.push_value(Value::Integer(1));
+        match machine.len() {
+            Err(Error::UnsupportedOperand {
+                op: "len",
+                operand: ValueKind::Integer,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn call_method() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+        let ident = |name: &str| Ident::new_string(&arena, name.to_string());
+
+        machine.make_list(0).unwrap();
+        let list = machine.pop().unwrap();
+
+        machine.push_value(list.clone());
+        machine.push_value(Value::Integer(1));
+        machine.call_method(ident("push"), 1).unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Unit);
+        machine.push_value(list.clone());
+        machine.call_method(ident("len"), 0).unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(1));
+
+        machine.push_value(Value::Integer(7));
+        machine.push_value(Value::Integer(-2));
+        machine.call_method(ident("div_floor"), 1).unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(-4));
+        machine.push_value(Value::Float(7.5));
+        machine.push_value(Value::Integer(2));
+        machine.call_method(ident("div_floor"), 1).unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Float(3.0));
+
+        machine.push_value(list.clone());
+        machine.push_value(Value::Integer(1));
+        match machine.call_method(ident("len"), 1) {
+            Err(Error::WrongArity {
+                expected: 0,
+                received: 1,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        machine.push_value(list);
+        match machine.call_method(ident("missing"), 0) {
+            Err(Error::MethodNotFound { .. }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        machine.push_value(Value::Integer(1));
+        match machine.call_method(ident("len"), 0) {
+            Err(Error::MethodNotFound { .. }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn maps() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+        let string = |string: &str| {
+            Value::String(String::new(&arena, string.to_string()))
+        };
+        let pair = Value::Tuple(Tuple::new(
+            &arena,
+            vec![Value::Integer(1), Value::Boolean(true)],
+        ));
+
+        machine.push_value(string("b"));
+        machine.push_value(Value::Integer(1));
+        machine.push_value(pair.clone());
+        machine.push_value(Value::Integer(2));
+        machine.make_map(2).unwrap();
+        let map = machine.pop().unwrap();
+
+        machine.push_value(map.clone());
+        machine.push_value(string("a"));
+        machine.push_value(Value::Integer(3));
+        machine.map_insert().unwrap();
+        machine.push_value(map.clone());
+        machine.push_value(string("b"));
+        machine.push_value(Value::Integer(4));
+        machine.set_index().unwrap();
+
+        machine.push_value(map.clone());
+        machine.push_value(Value::Tuple(Tuple::new(
+            &arena,
+            vec![Value::Integer(1), Value::Boolean(true)],
+        )));
+        machine.index().unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(2));
+        machine.push_value(map.clone());
+        machine.push_value(Value::Tuple(Tuple::new(
+            &arena,
+            vec![Value::Float(1.0), Value::Boolean(true)],
+        )));
+        machine.index().unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(2));
+        machine.push_value(map.clone());
+        machine.push_value(string("b"));
+        machine.index().unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(4));
+
+        machine.push_value(map.clone());
+        machine.push_value(pair);
+        machine.map_remove().unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(2));
+        machine.push_value(map.clone());
+        machine.push_value(string("a"));
+        machine.map_contains().unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(true));
+        machine.push_value(map.clone());
+        machine.len().unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(2));
+
+        machine.push_value(map.clone());
+        machine.map_keys().unwrap();
+        match machine.pop().unwrap() {
+            Value::List(keys) => {
+                let keys: Vec<_> = (0..keys.len())
+                    .map(|i| match keys.get(i).unwrap() {
+                        Value::String(key) => key.string().clone(),
+                        key => panic!("unexpected key: {:?}", key),
+                    })
+                    .collect();
+                assert_eq!(keys, vec!["b", "a"]);
+            }
+            value => panic!("unexpected value: {:?}", value),
+        }
+
+        machine.push_value(map.clone());
+        machine.push_value(string("c"));
+        match machine.index() {
+            Err(Error::KeyNotFound) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        machine.push_value(map.clone());
+        machine.push_value(string("c"));
+        match machine.map_remove() {
+            Err(Error::KeyNotFound) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        machine.push_value(map);
+        machine.push_value(Value::Tuple(Tuple::new(
+            &arena,
+            vec![Value::Float(f64::NAN)],
+        )));
+        machine.push_value(Value::Integer(5));
+        match machine.map_insert() {
+            Err(Error::UnhashableKey {
+                kind: ValueKind::Float,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn strings() {
+        let arena = Arena::new();
+        let mut machine = Machine::new(&arena);
+        let ident = |name: &str| Ident::new_string(&arena, name.to_string());
+        let string =
+            |string: &str| Value::String(String::from_str(&arena, string));
+
+        machine.push_value(string("hé"));
+        machine.push_value(string("llo"));
+        machine.add().unwrap();
+        let hello = machine.pop().unwrap();
+        assert_eq!(hello, string("héllo"));
+
+        machine.push_value(hello.clone());
+        machine.len().unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(5));
+        machine.push_value(hello.clone());
+        machine.call_method(ident("byte_len"), 0).unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(6));
+
+        machine.push_value(hello.clone());
+        machine.push_value(Value::Integer(1));
+        machine.index().unwrap();
+        assert_eq!(machine.pop().unwrap(), string("é"));
+        machine.push_value(hello.clone());
+        machine.push_value(Value::Integer(1));
+        machine.push_value(Value::Integer(3));
+        machine.call_method(ident("slice"), 2).unwrap();
+        assert_eq!(machine.pop().unwrap(), string("él"));
+
+        machine.push_value(hello.clone());
+        machine.push_value(Value::Integer(5));
+        match machine.index() {
+            Err(Error::IndexOutOfBounds { index: 5, len: 5 }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        machine.push_value(hello.clone());
+        machine.push_value(Value::Integer(3));
+        machine.push_value(Value::Integer(6));
+        match machine.slice() {
+            Err(Error::InvalidSlice {
+                start: 3,
+                end: 6,
+                len: 5,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        machine.push_value(hello.clone());
+        machine.push_value(string("lo"));
+        machine.call_method(ident("find"), 1).unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Integer(3));
+        machine.push_value(hello.clone());
+        machine.push_value(string("x"));
+        machine.call_method(ident("find"), 1).unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Unit);
+        machine.push_value(hello.clone());
+        machine.push_value(string("él"));
+        machine.call_method(ident("contains"), 1).unwrap();
+        assert_eq!(machine.pop().unwrap(), Value::Boolean(true));
+        machine.push_value(hello.clone());
+        machine.push_value(string("l"));
+        machine.push_value(string("L"));
+        machine.call_method(ident("replace"), 2).unwrap();
+        assert_eq!(machine.pop().unwrap(), string("héLLo"));
+
+        machine.push_value(string("a,b,,c"));
+        machine.push_value(string(","));
+        machine.call_method(ident("split"), 1).unwrap();
+        let parts = machine.pop().unwrap();
+        let expected = List::new(
+            &arena,
+            vec![string("a"), string("b"), string(""), string("c")],
+        );
+        assert_eq!(parts, Value::List(expected));
+        machine.push_value(hello.clone());
+        machine.push_value(string(""));
+        match machine.call_method(ident("split"), 1) {
+            Err(Error::EmptySeparator) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        machine.push_value(hello.clone());
+        machine.call_method(ident("to_uppercase"), 0).unwrap();
+        assert_eq!(machine.pop().unwrap(), string("HÉLLO"));
+        machine.push_value(string("HÉLLO"));
+        machine.call_method(ident("to_lowercase"), 0).unwrap();
+        assert_eq!(machine.pop().unwrap(), hello.clone());
+
+        machine.push_value(hello.clone());
+        machine.push_value(Value::Integer(1));
+        match machine.call_method(ident("contains"), 1) {
+            Err(Error::InvalidValueKind {
+                expected: ValueKind::String,
+                received: ValueKind::Integer,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        machine.push_value(hello);
+        machine.push_value(Value::Integer(1));
+        match machine.add() {
+            Err(Error::UnsupportedOperands {
+                op: "+",
+                left: ValueKind::String,
+                right: ValueKind::Integer,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
 }
@@ -0,0 +1,99 @@
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::process;
+
+use eko::compiler::diagnostic::{Diagnostic, Renderer};
+use eko::compiler::formatter::Formatter;
+
+const USAGE: &str = "usage: eko fmt [--check] [--max-width <width>] <file>...";
+
+fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+
+    let code = match args.split_first() {
+        Some((command, args)) if command == "fmt" => fmt(args),
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    };
+    process::exit(code);
+}
+
+/// Formats the given files in place, or with `--check` only reports the
+/// files that would change.
+fn fmt(args: &[String]) -> i32 {
+    let mut formatter = Formatter::new();
+    let mut check = false;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--max-width" => match args.next().and_then(|w| w.parse().ok()) {
+                Some(max_width) => formatter = formatter.max_width(max_width),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            },
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+
+    let colored = io::stderr().is_terminal();
+    let mut code = 0;
+
+    for path in paths {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("error: {}: {}", path, err);
+                code = 1;
+                continue;
+            }
+        };
+
+        let report = |diagnostics: &[Diagnostic]| {
+            let renderer = Renderer::new(path, &source).colored(colored);
+            for diagnostic in diagnostics {
+                eprintln!("{}", renderer.render(diagnostic));
+            }
+        };
+
+        if check {
+            match formatter.check(&source) {
+                Ok(None) => {}
+                Ok(Some(diagnostic)) => {
+                    report(&[diagnostic]);
+                    code = 1;
+                }
+                Err(diagnostics) => {
+                    report(&diagnostics);
+                    code = 1;
+                }
+            }
+        } else {
+            match formatter.format(&source) {
+                Ok(formatted) if formatted == source => {}
+                Ok(formatted) => {
+                    if let Err(err) = fs::write(path, formatted) {
+                        eprintln!("error: {}: {}", path, err);
+                        code = 1;
+                    }
+                }
+                Err(diagnostics) => {
+                    report(&diagnostics);
+                    code = 1;
+                }
+            }
+        }
+    }
+    code
+}